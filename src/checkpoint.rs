@@ -0,0 +1,179 @@
+use crate::extractor::KeyRange;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Progress of every key range is written at most this often, phase changes always are.
+/// A crash loses at most that much progress: resuming copies those rows again.
+const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Migration steps of a table, in the order they complete. Indexes are part of the
+/// `SHOW CREATE TABLE` statement, so they are done along with the schema.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Phase {
+    Schema,
+    Data,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct TableCheckpoint {
+    /// Last phase that finished, None when the table was not even created.
+    #[serde(default)]
+    pub(crate) phase: Option<Phase>,
+    /// Key ranges the data is read in, each with the last key committed to the target.
+    /// Empty when the table is not read by key, it can then only be copied again in full.
+    #[serde(default)]
+    pub(crate) ranges: Vec<KeyRange>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct CheckpointState {
+    tables: BTreeMap<String, TableCheckpoint>,
+}
+
+/// State file recording how far each table got, so an interrupted migration can resume
+/// instead of starting over. Shared by all the table tasks.
+pub(crate) struct Checkpoint {
+    path: PathBuf,
+    state: Mutex<CheckpointState>,
+    last_saved: Mutex<Instant>,
+}
+
+impl Checkpoint {
+    /// Start a fresh checkpoint, any existing file is overwritten on the first save.
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            state: Mutex::new(CheckpointState::default()),
+            last_saved: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Read the checkpoint of a previous run to resume it.
+    pub(crate) fn load(path: PathBuf) -> Result<Self, String> {
+        let file = std::fs::File::open(&path)
+            .map_err(|e| format!("cannot open checkpoint {}: {}", path.display(), e))?;
+        let state = serde_json::from_reader(std::io::BufReader::new(file))
+            .map_err(|e| format!("cannot read checkpoint {}: {}", path.display(), e))?;
+
+        Ok(Self {
+            path,
+            state: Mutex::new(state),
+            last_saved: Mutex::new(Instant::now()),
+        })
+    }
+
+    pub(crate) fn table(&self, name: &str) -> TableCheckpoint {
+        self.state
+            .lock()
+            .unwrap()
+            .tables
+            .get(name)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn finish_phase(&self, name: &str, phase: Phase) {
+        let mut state = self.state.lock().unwrap();
+        let table = state.tables.entry(name.to_string()).or_default();
+        table.phase = Some(phase);
+
+        // Ranges only matter while the data is being copied.
+        if phase == Phase::Data {
+            table.ranges.clear();
+        }
+
+        self.save(&state);
+    }
+
+    pub(crate) fn set_ranges(&self, name: &str, ranges: Vec<KeyRange>) {
+        let mut state = self.state.lock().unwrap();
+        state.tables.entry(name.to_string()).or_default().ranges = ranges;
+
+        self.save(&state);
+    }
+
+    fn commit_key(&self, name: &str, range: usize, key: i128) {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(range) = state
+            .tables
+            .get_mut(name)
+            .and_then(|table| table.ranges.get_mut(range))
+        {
+            range.committed = Some(key);
+        }
+
+        let mut last_saved = self.last_saved.lock().unwrap();
+        if last_saved.elapsed() >= SAVE_INTERVAL {
+            *last_saved = Instant::now();
+            self.save(&state);
+        }
+    }
+
+    /// Written to a temporary file then renamed over the previous one, so a crash while
+    /// saving never leaves a truncated checkpoint behind.
+    fn save(&self, state: &CheckpointState) {
+        let temp_path = self.path.with_extension("tmp");
+
+        let result = serde_json::to_vec_pretty(state)
+            .map_err(std::io::Error::other)
+            .and_then(|content| std::fs::write(&temp_path, content))
+            .and_then(|_| std::fs::rename(&temp_path, &self.path));
+
+        if let Err(e) = result {
+            tracing::error!("failed to save checkpoint {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Follows the batches of one key range as they land in the target, to record in the
+/// checkpoint the highest key up to which every row is committed. Batches are inserted
+/// concurrently and may finish out of order, so a batch only moves the key forward once
+/// all the batches read before it are committed too.
+#[derive(Clone)]
+pub(crate) struct RangeCommits {
+    checkpoint: Arc<Checkpoint>,
+    table: String,
+    range: usize,
+    pending: Arc<Mutex<PendingBatches>>,
+}
+
+#[derive(Default)]
+struct PendingBatches {
+    next: u64,
+    done: HashMap<u64, Option<i128>>,
+}
+
+impl RangeCommits {
+    pub(crate) fn new(checkpoint: Arc<Checkpoint>, table: String, range: usize) -> Self {
+        Self {
+            checkpoint,
+            table,
+            range,
+            pending: Arc::new(Mutex::new(PendingBatches::default())),
+        }
+    }
+
+    /// Record that the batch read in position `sequence` of the range, whose last row had
+    /// the key `last_key`, is committed. A batch without a key still lets the ones after
+    /// it move the key forward.
+    pub(crate) fn committed(&self, sequence: u64, last_key: Option<i128>) {
+        let mut pending = self.pending.lock().unwrap();
+        let PendingBatches { next, done } = &mut *pending;
+        done.insert(sequence, last_key);
+
+        let mut committed = None;
+        while let Some(key) = done.remove(next) {
+            committed = key.or(committed);
+            *next += 1;
+        }
+
+        if let Some(key) = committed {
+            self.checkpoint.commit_key(&self.table, self.range, key);
+        }
+    }
+}
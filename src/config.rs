@@ -1,4 +1,4 @@
-use crate::transformer::Transformer;
+use crate::transformer::TransformerPipeline;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -8,9 +8,20 @@ pub(crate) struct Config {
     pub(crate) target: DatabaseConfig,
     #[serde(default)]
     pub(crate) create: CreateConfig,
-    #[serde(with = "serde_yaml::with::singleton_map_recursive")]
     #[serde(default)]
     pub(crate) migrate: MigrateConfig,
+    #[serde(default)]
+    pub(crate) subset: Option<SubsetConfig>,
+}
+
+/// Copy a referentially intact slice of the database instead of whole tables.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct SubsetConfig {
+    /// Tables the slice starts from, with the condition selecting their rows.
+    pub(crate) roots: HashMap<String, String>,
+    /// Also take the rows referencing the selected ones, and the rows referencing those.
+    #[serde(default = "default_true")]
+    pub(crate) follow_children: bool,
 }
 
 #[derive(Serialize, Deserialize, Default, Debug, Clone)]
@@ -24,6 +35,9 @@ pub(crate) struct DatabaseConfig {
     pub(crate) dsn: String,
     #[serde(default = "default_max_connections")]
     pub(crate) max_connections: u32,
+    /// Read every table from one single point in time. Only meaningful for the source.
+    #[serde(default = "default_false")]
+    pub(crate) consistent_snapshot: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -35,18 +49,49 @@ pub(crate) struct MigrateConfig {
 pub(crate) struct MigrateTableConfig {
     #[serde(default = "default_batch_size")]
     pub(crate) batch_size: usize,
+    /// A batch is sent as soon as its values add up to this many bytes, even before it
+    /// reaches `batch_size` rows, so wide rows neither balloon memory nor exceed the target
+    /// `max_allowed_packet`.
+    #[serde(default = "default_batch_max_bytes")]
+    pub(crate) batch_max_bytes: usize,
+    /// Batches read but not inserted yet, above which reading waits for the target.
+    #[serde(default = "default_max_in_flight_batches")]
+    pub(crate) max_in_flight_batches: usize,
     #[serde(default = "default_false")]
     pub(crate) skip_data: bool,
     #[serde(default)]
-    pub(crate) transformers: HashMap<String, Transformer>,
+    pub(crate) transformers: HashMap<String, TransformerPipeline>,
     #[serde(default)]
     pub(crate) load_strategy: LoadStrategy,
     #[serde(default)]
     pub(crate) select_query: Option<String>,
+    /// Condition the rows must match to be copied, added to the generated select and count.
+    #[serde(default, rename = "where")]
+    pub(crate) where_clause: Option<String>,
+    /// Copy at most this many rows. The table is then read in one go, ignoring parallelism.
+    #[serde(default)]
+    pub(crate) limit: Option<u64>,
+    /// ORDER BY clause of the generated select, mostly useful with `limit`. The table is then
+    /// read in one go, ignoring parallelism.
+    #[serde(default)]
+    pub(crate) order_by: Option<String>,
+    /// Number of source connections reading the table at once, each one streaming its own
+    /// range of the key.
+    #[serde(default = "default_parallelism")]
+    pub(crate) parallelism: usize,
+    /// Integer column to split the table on, the primary key when not set.
+    #[serde(default)]
+    pub(crate) chunk_key: Option<String>,
+    /// Conditions selecting the rows of the table in the subset, matching disjoint rows and
+    /// each read by a query of its own. Computed at startup, empty when the table is not
+    /// part of the subset.
+    #[serde(skip)]
+    pub(crate) subset: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Default)]
 pub enum LoadStrategy {
+    #[default]
     Insert,
     LoadData,
 }
@@ -54,28 +99,99 @@ fn default_batch_size() -> usize {
     1000
 }
 
+fn default_batch_max_bytes() -> usize {
+    16 * 1024 * 1024
+}
+
+fn default_max_in_flight_batches() -> usize {
+    4
+}
+
+fn default_parallelism() -> usize {
+    1
+}
+
 fn default_false() -> bool {
     false
 }
 
+fn default_true() -> bool {
+    true
+}
+
 fn default_max_connections() -> u32 {
     10
 }
 
+impl MigrateConfig {
+    /// Options the configuration combines but which cannot work together, one message each.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        for (table, table_config) in &self.tables {
+            // The custom query replaces the generated select these options build, while the
+            // count sizing the progress bar would still apply them.
+            if table_config.select_query.is_some()
+                && (table_config.where_clause.is_some()
+                    || table_config.limit.is_some()
+                    || table_config.order_by.is_some())
+            {
+                problems.push(format!(
+                    "{}: where, limit and order_by cannot be combined with select_query, put them in the query instead",
+                    table
+                ));
+            }
+        }
+
+        problems.sort();
+
+        problems
+    }
+
+    /// Options of the tables part of the subset which would copy other rows than those of
+    /// the subset, one message each.
+    pub(crate) fn subset_problems<'a>(
+        &self,
+        subset_tables: impl IntoIterator<Item = &'a String>,
+    ) -> Vec<String> {
+        let mut problems = subset_tables
+            .into_iter()
+            .filter_map(|table| {
+                let table_config = self.tables.get(table)?;
+
+                // The custom query would not apply the subset, a limit would drop rows other
+                // tables of the subset reference.
+                (table_config.select_query.is_some() || table_config.limit.is_some()).then(|| {
+                    format!(
+                        "{}: select_query and limit cannot be used on a table of the subset",
+                        table
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+
+        problems.sort();
+
+        problems
+    }
+}
+
 impl Default for MigrateTableConfig {
     fn default() -> Self {
         Self {
             batch_size: default_batch_size(),
+            batch_max_bytes: default_batch_max_bytes(),
+            max_in_flight_batches: default_max_in_flight_batches(),
             skip_data: default_false(),
             transformers: HashMap::new(),
             load_strategy: LoadStrategy::Insert,
             select_query: None,
+            where_clause: None,
+            limit: None,
+            order_by: None,
+            parallelism: default_parallelism(),
+            chunk_key: None,
+            subset: Vec::new(),
         }
     }
 }
-
-impl Default for LoadStrategy {
-    fn default() -> Self {
-        Self::Insert
-    }
-}
@@ -6,6 +6,15 @@ use crate::value::ValueError;
 pub enum ExtractorError {
     Sqlx(sqlx::Error),
     ValueError(ValueError),
+    BatchFailed(Vec<BatchFailure>),
+    Config(String),
+}
+
+/// A batch of rows the target did not accept.
+#[derive(Debug)]
+pub struct BatchFailure {
+    pub batch_id: u32,
+    pub error: String,
 }
 
 impl From<sqlx::Error> for ExtractorError {
@@ -25,8 +34,19 @@ impl std::fmt::Display for ExtractorError {
         match self {
             ExtractorError::Sqlx(err) => write!(f, "Sqlx error: {}", err),
             ExtractorError::ValueError(err) => write!(f, "Value error: {}", err),
+            ExtractorError::Config(err) => write!(f, "Configuration error: {}", err),
+            ExtractorError::BatchFailed(failures) => {
+                write!(f, "{} batch(es) failed:", failures.len())?;
+
+                for failure in failures {
+                    write!(f, " [batch {}] {};", failure.batch_id, failure.error)?;
+                }
+
+                Ok(())
+            }
         }
     }
 }
 
+pub(crate) use table::KeyRange;
 pub use table::TableExtractor;
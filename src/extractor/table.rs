@@ -1,20 +1,83 @@
+use crate::checkpoint::{Checkpoint, Phase, RangeCommits};
 use crate::config::{LoadStrategy, MigrateTableConfig};
-use crate::extractor::ExtractorError;
+use crate::extractor::{BatchFailure, ExtractorError};
+use crate::transformer::{
+    ColumnSamples, TransformerPipeline, UniqueKeys, transform_order, transform_row,
+};
 use crate::value::MysqlValueDecoded;
 use futures::TryStreamExt;
 use indicatif::ProgressBar;
-use sqlx::{AssertSqlSafe, Executor, MySqlPool, QueryBuilder, Row, ValueRef};
+use serde::{Deserialize, Serialize};
+use sqlx::mysql::MySqlDatabaseError;
+use sqlx::pool::PoolConnection;
+use sqlx::{AssertSqlSafe, Executor, MySql, MySqlPool, QueryBuilder, Row, ValueRef};
+use std::collections::HashMap;
 use std::io::Write;
 use std::ops::DerefMut;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use tokio::sync::Semaphore;
+use tokio::task::{JoinError, JoinSet};
 
-const SELECT_COLUMNS_FOR_INSERT: &str = "SELECT `COLUMN_NAME` AS `Field`, `COLUMN_TYPE` AS `Type`, `IS_NULLABLE` AS `Null`, `COLUMN_KEY` AS `Key`, `COLUMN_DEFAULT` AS `Default`, `EXTRA` AS `Extra`, `COLUMN_COMMENT` AS `Comment` FROM `INFORMATION_SCHEMA`.`COLUMNS` WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = ?";
+/// ER_NET_PACKET_TOO_LARGE, a statement larger than the target `max_allowed_packet`.
+const ER_NET_PACKET_TOO_LARGE: u16 = 1153;
+/// ER_TOO_LONG_STRING, a value built by the statement larger than `max_allowed_packet`.
+const ER_TOO_LONG_STRING: u16 = 1162;
+
+const SELECT_COLUMNS_FOR_INSERT: &str = "SELECT `COLUMN_NAME` AS `Field`, `COLUMN_TYPE` AS `Type`, `IS_NULLABLE` AS `Null`, `COLUMN_KEY` AS `Key`, `COLUMN_DEFAULT` AS `Default`, `EXTRA` AS `Extra`, `COLUMN_COMMENT` AS `Comment` FROM `INFORMATION_SCHEMA`.`COLUMNS` WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = ? ORDER BY `ORDINAL_POSITION`";
+const SELECT_UNIQUE_INDEXES: &str = "SELECT `INDEX_NAME`, `COLUMN_NAME` FROM `INFORMATION_SCHEMA`.`STATISTICS` WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = ? AND `NON_UNIQUE` = 0 AND `COLUMN_NAME` IS NOT NULL ORDER BY `INDEX_NAME`, `SEQ_IN_INDEX`";
+const SELECT_UNIQUE_KEY: &str = "SELECT COUNT(*) FROM `INFORMATION_SCHEMA`.`STATISTICS` AS s JOIN `INFORMATION_SCHEMA`.`COLUMNS` AS c ON c.TABLE_SCHEMA = s.TABLE_SCHEMA AND c.TABLE_NAME = s.TABLE_NAME AND c.COLUMN_NAME = s.COLUMN_NAME WHERE s.TABLE_SCHEMA = DATABASE() AND s.TABLE_NAME = ? AND s.COLUMN_NAME = ? AND s.NON_UNIQUE = 0 AND s.SUB_PART IS NULL AND c.IS_NULLABLE = 'NO' AND NOT EXISTS (SELECT 1 FROM `INFORMATION_SCHEMA`.`STATISTICS` AS o WHERE o.TABLE_SCHEMA = s.TABLE_SCHEMA AND o.TABLE_NAME = s.TABLE_NAME AND o.INDEX_NAME = s.INDEX_NAME AND o.SEQ_IN_INDEX > 1)";
+const SELECT_PRIMARY_KEY: &str = "SELECT `COLUMN_NAME` FROM `INFORMATION_SCHEMA`.`KEY_COLUMN_USAGE` WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = ? AND CONSTRAINT_NAME = 'PRIMARY' ORDER BY ORDINAL_POSITION";
+
+/// Part of a table read on its own connection: the rows whose key is in `[lower, upper)`,
+/// an open bound meaning no limit on that side.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct KeyRange {
+    key: String,
+    lower: Option<i128>,
+    upper: Option<i128>,
+    /// Last key committed to the target when the table is checkpointed, rows up to it are
+    /// not read again on resume.
+    pub(crate) committed: Option<i128>,
+}
+
+impl KeyRange {
+    /// Condition selecting the rows of the range not committed yet, None for all rows.
+    fn condition(&self) -> Option<String> {
+        let mut parts = Vec::new();
+
+        match (self.committed, self.lower) {
+            (Some(committed), _) => parts.push(format!("`{}` > {}", self.key, committed)),
+            (None, Some(lower)) => parts.push(format!("`{}` >= {}", self.key, lower)),
+            (None, None) => (),
+        }
+
+        if let Some(upper) = self.upper {
+            // NULL keys sort first, so the first range reads them before committing anything.
+            if self.lower.is_none() && self.committed.is_none() {
+                parts.push(format!(
+                    "(`{}` < {} OR `{}` IS NULL)",
+                    self.key, upper, self.key
+                ));
+            } else {
+                parts.push(format!("`{}` < {}", self.key, upper));
+            }
+        }
+
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join(" AND "))
+        }
+    }
+}
 
 pub struct TableExtractor {
     source_pool: Arc<MySqlPool>,
     target_pool: Arc<MySqlPool>,
     migrate_table_config: MigrateTableConfig,
     name: String,
+    checkpoint: Option<Arc<Checkpoint>>,
 }
 
 impl TableExtractor {
@@ -23,21 +86,32 @@ impl TableExtractor {
         target_pool: Arc<MySqlPool>,
         migrate_table_config: MigrateTableConfig,
         name: String,
+        checkpoint: Option<Arc<Checkpoint>>,
     ) -> Self {
         Self {
             source_pool,
             target_pool,
             migrate_table_config,
             name,
+            checkpoint,
         }
     }
 
     pub async fn extract(&mut self, progress_bar: &ProgressBar) -> Result<(), ExtractorError> {
+        let mut resumed = self
+            .checkpoint
+            .as_ref()
+            .map(|checkpoint| checkpoint.table(&self.name))
+            .unwrap_or_default();
+
+        if resumed.phase == Some(Phase::Data) {
+            return Ok(());
+        }
+
         // keep same connection to disable key check
         // first acquire source_conn to ensure slot available to handle table
         let mut source_conn = self.source_pool.acquire().await?;
         let mut conn = self.target_pool.acquire().await?;
-        progress_bar.set_message(format!("drop target table {}", self.name));
 
         // disable key check
         let disable_key_check_query = "SET FOREIGN_KEY_CHECKS=0";
@@ -45,30 +119,84 @@ impl TableExtractor {
             .execute(conn.deref_mut())
             .await?;
 
-        // delete table if exists in target
-        let delete_query = format!("DROP TABLE IF EXISTS `{}`", self.name);
-        sqlx::query(AssertSqlSafe(delete_query))
-            .execute(conn.deref_mut())
-            .await?;
+        if resumed.phase.is_none() {
+            progress_bar.set_message(format!("drop target table {}", self.name));
 
-        // write table schema
-        progress_bar.set_message(format!("create target table {}", self.name));
-        let query = format!("SHOW CREATE TABLE `{}`", self.name);
-        let create_table_row = sqlx::query(AssertSqlSafe(query))
-            .fetch_one(source_conn.deref_mut())
-            .await?;
+            // delete table if exists in target
+            let delete_query = format!("DROP TABLE IF EXISTS `{}`", self.name);
+            sqlx::query(AssertSqlSafe(delete_query))
+                .execute(conn.deref_mut())
+                .await?;
 
-        let create_table_query = create_table_row.get::<String, usize>(1);
+            // write table schema
+            progress_bar.set_message(format!("create target table {}", self.name));
+            let query = format!("SHOW CREATE TABLE `{}`", self.name);
+            let create_table_row = sqlx::query(AssertSqlSafe(query))
+                .fetch_one(source_conn.deref_mut())
+                .await?;
 
-        let _ = sqlx::query(AssertSqlSafe(create_table_query))
-            .execute(conn.deref_mut())
-            .await?;
+            let create_table_query = create_table_row.get::<String, usize>(1);
+
+            let _ = sqlx::query(AssertSqlSafe(create_table_query))
+                .execute(conn.deref_mut())
+                .await?;
+
+            if let Some(checkpoint) = &self.checkpoint {
+                checkpoint.finish_phase(&self.name, Phase::Schema);
+            }
+        }
 
         // write table data
         if self.migrate_table_config.skip_data {
+            if let Some(checkpoint) = &self.checkpoint {
+                checkpoint.finish_phase(&self.name, Phase::Data);
+            }
+
             return Ok(());
         }
 
+        // The table was created by a previous run which stopped while copying its data: rows
+        // past the last committed key of each range may still have landed, as batches commit
+        // out of order, and a table not read by key has no committed key at all. Delete them
+        // so the copy can go on from there without duplicates.
+        let ranges = if resumed.phase == Some(Phase::Schema) {
+            progress_bar.set_message(format!("resume target table {}", self.name));
+
+            // The configuration may have changed since: a transformed key is no longer the one
+            // of the target rows, and a range read by several subset queries is not in key
+            // order.
+            if self.migrate_table_config.subset.len() > 1
+                || resumed.ranges.iter().any(|range| {
+                    self.migrate_table_config
+                        .transformers
+                        .contains_key(&range.key)
+                })
+            {
+                resumed.ranges.clear();
+            }
+
+            if resumed.ranges.is_empty() {
+                let truncate_query = format!("TRUNCATE TABLE `{}`", self.name);
+                sqlx::query(AssertSqlSafe(truncate_query))
+                    .execute(conn.deref_mut())
+                    .await?;
+            }
+
+            for range in resumed.ranges.iter() {
+                let delete_query = match range.condition() {
+                    Some(condition) => format!("DELETE FROM `{}` WHERE {}", self.name, condition),
+                    None => format!("DELETE FROM `{}`", self.name),
+                };
+                sqlx::query(AssertSqlSafe(delete_query))
+                    .execute(conn.deref_mut())
+                    .await?;
+            }
+
+            resumed.ranges
+        } else {
+            Vec::new()
+        };
+
         drop(conn);
         progress_bar.set_message(format!("migrate table data {}", self.name));
 
@@ -79,93 +207,785 @@ impl TableExtractor {
             .await?;
 
         let mut indexed_fields = Vec::new();
+        let mut max_lengths = Vec::new();
 
         for row in columns_query {
             let field = row.get::<&str, &str>("Field");
             indexed_fields.push(field.to_string());
+            max_lengths.push(max_length(row.get::<&str, &str>("Type")));
         }
 
+        let transform_order =
+            transform_order(&self.migrate_table_config.transformers, &indexed_fields)
+                .map_err(|e| ExtractorError::Config(format!("{}: {}", self.name, e)))?;
+
+        // Unique indexes, to keep transformed values from colliding in them.
+        let unique_keys = if self.migrate_table_config.transformers.is_empty() {
+            UniqueKeys::new(
+                Vec::new(),
+                &indexed_fields,
+                &max_lengths,
+                &self.migrate_table_config.transformers,
+            )
+        } else {
+            let index_rows = sqlx::query(SELECT_UNIQUE_INDEXES)
+                .bind(&self.name)
+                .fetch_all(source_conn.deref_mut())
+                .await?;
+
+            let mut indexes: Vec<(String, Vec<String>)> = Vec::new();
+            for row in index_rows {
+                let name = row.get::<String, usize>(0);
+                let column = row.get::<String, usize>(1);
+
+                match indexes.last_mut() {
+                    Some((last, columns)) if *last == name => columns.push(column),
+                    _ => indexes.push((name, vec![column])),
+                }
+            }
+
+            UniqueKeys::new(
+                indexes,
+                &indexed_fields,
+                &max_lengths,
+                &self.migrate_table_config.transformers,
+            )
+        };
+
+        // Rows the resumed run already copied hold keys as well.
+        if !ranges.is_empty() && !unique_keys.is_empty() {
+            progress_bar.set_message(format!("read unique keys of target table {}", self.name));
+
+            let mut target_conn = self.target_pool.acquire().await?;
+            let query = format!("SELECT * FROM `{}`", self.name);
+            let mut target_rows = target_conn.fetch(AssertSqlSafe(query));
+
+            while let Some(row) = target_rows.try_next().await? {
+                let mut values = Vec::with_capacity(row.len());
+
+                for i in 0..row.len() {
+                    let value = ValueRef::to_owned(&row.try_get_raw(i)?);
+                    values.push(MysqlValueDecoded::try_from(value)?);
+                }
+
+                unique_keys.record(&values);
+            }
+
+            progress_bar.set_message(format!("migrate table data {}", self.name));
+        }
+
+        // Samples are read by a query of their own: with a limit, both must read the same
+        // rows, so in the same order.
+        if self.migrate_table_config.limit.is_some()
+            && self.migrate_table_config.order_by.is_none()
+            && self
+                .migrate_table_config
+                .transformers
+                .values()
+                .any(TransformerPipeline::needs_samples)
+        {
+            let key = sqlx::query(SELECT_PRIMARY_KEY)
+                .bind(&self.name)
+                .fetch_all(source_conn.deref_mut())
+                .await?
+                .iter()
+                .map(|row| format!("`{}`", row.get::<String, usize>(0)))
+                .collect::<Vec<_>>();
+
+            if key.is_empty() {
+                tracing::warn!(
+                    "table {} has no primary key to order it by, its samples may come from other rows than the ones copied",
+                    self.name
+                );
+            } else {
+                self.migrate_table_config.order_by = Some(key.join(", "));
+            }
+        }
+
+        // Ranges from the checkpoint were only recorded for a key they can resume by.
+        let mut resumable_ranges = !ranges.is_empty();
+
         // get data
-        let select_query = self
+        let chunks = match &self.migrate_table_config.select_query {
+            Some(select_query) => vec![(vec![select_query.clone()], None)],
+            None => {
+                let ranges = if ranges.is_empty() {
+                    let (ranges, resumable) = self.plan_ranges(source_conn.deref_mut()).await?;
+
+                    if let Some(checkpoint) = &self.checkpoint
+                        && resumable
+                    {
+                        checkpoint.set_ranges(&self.name, ranges.clone());
+                    }
+
+                    resumable_ranges = resumable;
+                    ranges
+                } else {
+                    ranges
+                };
+
+                if ranges.is_empty() {
+                    vec![(self.select_queries(None), None)]
+                } else {
+                    ranges
+                        .into_iter()
+                        .enumerate()
+                        .map(|(index, range)| {
+                            (self.select_queries(Some(&range)), Some((index, range)))
+                        })
+                        .collect()
+                }
+            }
+        };
+
+        // The first range reuses the connection the table already holds, the others get
+        // their own. The caller reserved as many table slots as the configured parallelism,
+        // so these are available rather than taken away from another table.
+        let mut source_conns = vec![source_conn];
+        for _ in 1..chunks.len() {
+            source_conns.push(self.source_pool.acquire().await?);
+        }
+
+        let samples = self
+            .read_samples(&chunks, source_conns[0].deref_mut())
+            .await?;
+
+        let shared = CopyShared {
+            indexed_fields: indexed_fields.as_slice(),
+            transform_order,
+            unique_keys,
+            samples,
+            batch_ids: AtomicU32::new(0),
+            cancelled: AtomicBool::new(false),
+            in_flight: Arc::new(Semaphore::new(
+                self.migrate_table_config.max_in_flight_batches.max(1),
+            )),
+            progress_bar,
+        };
+        let shared = &shared;
+        let this = &*self;
+
+        let results = futures::future::join_all(chunks.into_iter().zip(source_conns).map(
+            |((select_queries, range), mut source_conn)| async move {
+                // Committed keys are only tracked for checkpointed tables read by a key they
+                // can resume by.
+                let commits = match (&this.checkpoint, range) {
+                    (Some(checkpoint), Some((index, range))) if resumable_ranges => shared
+                        .indexed_fields
+                        .iter()
+                        .position(|field| *field == range.key)
+                        .map(|key_index| {
+                            (
+                                key_index,
+                                RangeCommits::new(checkpoint.clone(), this.name.clone(), index),
+                            )
+                        }),
+                    _ => None,
+                };
+
+                this.copy_rows(source_conn.deref_mut(), select_queries, commits, shared)
+                    .await
+            },
+        ))
+        .await;
+
+        // Every range ran to its end, gather the failed batches of all of them.
+        let mut failed_batches = Vec::new();
+        for result in results {
+            match result {
+                Ok(()) => (),
+                Err(ExtractorError::BatchFailed(failures)) => failed_batches.extend(failures),
+                Err(err) => return Err(err),
+            }
+        }
+
+        if !failed_batches.is_empty() {
+            return Err(ExtractorError::BatchFailed(failed_batches));
+        }
+
+        if let Some(checkpoint) = &self.checkpoint {
+            checkpoint.finish_phase(&self.name, Phase::Data);
+        }
+
+        Ok(())
+    }
+
+    /// Read the columns whose transformers draw from their values, from all the rows about
+    /// to be copied, before copying them.
+    async fn read_samples(
+        &self,
+        chunks: &[Chunk],
+        source_conn: &mut sqlx::MySqlConnection,
+    ) -> Result<ColumnSamples, ExtractorError> {
+        let columns = self
             .migrate_table_config
-            .select_query
-            .clone()
-            .unwrap_or_else(|| format!("SELECT * FROM `{}`", self.name));
-        let mut select_stream = source_conn.fetch(AssertSqlSafe(select_query));
+            .transformers
+            .iter()
+            .filter(|(_, pipeline)| pipeline.needs_samples())
+            .map(|(column, _)| column.clone())
+            .collect::<Vec<_>>();
 
-        let batch_size = self.migrate_table_config.batch_size;
-        let strategy = self.migrate_table_config.load_strategy;
-        let mut rows = Vec::with_capacity(batch_size);
-        let mut batch_id = 0;
+        if columns.is_empty() {
+            return Ok(ColumnSamples::default());
+        }
+
+        let mut values = vec![Vec::new(); columns.len()];
 
-        while let Some(row) = select_stream.try_next().await? {
-            let mut values = Vec::with_capacity(row.len());
+        for select_query in chunks.iter().flat_map(|(select_queries, _)| select_queries) {
+            let sample_query = format!(
+                "SELECT {} FROM ({}) AS `samples`",
+                columns
+                    .iter()
+                    .map(|column| format!("`{}`", column))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                select_query
+            );
+            let mut rows = source_conn.fetch(AssertSqlSafe(sample_query));
 
-            for i in 0..row.len() {
-                let value = ValueRef::to_owned(&row.try_get_raw(i)?);
-                let mut decoded = MysqlValueDecoded::try_from(value)?;
+            while let Some(row) = rows.try_next().await? {
+                for (i, column_values) in values.iter_mut().enumerate() {
+                    let value = ValueRef::to_owned(&row.try_get_raw(i)?);
+                    column_values.push(MysqlValueDecoded::try_from(value)?);
+                }
+            }
+        }
+
+        Ok(ColumnSamples::new(
+            columns.into_iter().zip(values).collect(),
+        ))
+    }
+
+    /// Number of rows the table data is expected to hold, to size the progress bar.
+    pub async fn count(&self) -> Result<u64, ExtractorError> {
+        let mut count = 0;
+
+        for conditions in self.conditions(None) {
+            let mut count_query = format!("SELECT COUNT(*) FROM `{}`", self.name);
+
+            if !conditions.is_empty() {
+                count_query.push_str(&format!(" WHERE {}", conditions.join(" AND ")));
+            }
+
+            let row = sqlx::query(AssertSqlSafe(count_query))
+                .fetch_one(self.source_pool.as_ref())
+                .await?;
+            count += row.get::<i64, usize>(0) as u64;
+        }
+
+        Ok(match self.migrate_table_config.limit {
+            Some(limit) => count.min(limit),
+            None => count,
+        })
+    }
+
+    /// Conditions of each query reading the rows of the table, or of one range of its key:
+    /// one per subset condition, or a single one outside of the subset.
+    fn conditions(&self, range: Option<&KeyRange>) -> Vec<Vec<String>> {
+        let conditions = self
+            .migrate_table_config
+            .where_clause
+            .iter()
+            .map(|where_clause| format!("({})", where_clause))
+            .chain(range.and_then(KeyRange::condition))
+            .collect::<Vec<_>>();
 
-                if let Some(field) = indexed_fields.get(i) {
-                    if let Some(transformer) = self.migrate_table_config.transformers.get(field) {
-                        decoded = transformer.transform(decoded);
+        if self.migrate_table_config.subset.is_empty() {
+            return vec![conditions];
+        }
+
+        self.migrate_table_config
+            .subset
+            .iter()
+            .map(|subset| {
+                let mut conditions = conditions.clone();
+                conditions.push(format!("({})", subset));
+
+                conditions
+            })
+            .collect()
+    }
+
+    /// Queries reading the rows of the table, or of one range of its key, with the
+    /// configured filter, order and limit, one after the other.
+    fn select_queries(&self, range: Option<&KeyRange>) -> Vec<String> {
+        self.conditions(range)
+            .into_iter()
+            .map(|conditions| {
+                let mut select_query = format!("SELECT * FROM `{}`", self.name);
+
+                if !conditions.is_empty() {
+                    select_query.push_str(&format!(" WHERE {}", conditions.join(" AND ")));
+                }
+
+                match (&self.migrate_table_config.order_by, range) {
+                    (Some(order_by), _) => {
+                        select_query.push_str(&format!(" ORDER BY {}", order_by))
+                    }
+                    // A committed key only means something if the rows before it were all
+                    // read first.
+                    (None, Some(range)) if self.checkpoint.is_some() => {
+                        select_query.push_str(&format!(" ORDER BY `{}`", range.key))
                     }
+                    _ => (),
+                }
+
+                if let Some(limit) = self.migrate_table_config.limit {
+                    select_query.push_str(&format!(" LIMIT {}", limit));
                 }
 
-                values.push(decoded);
+                select_query
+            })
+            .collect()
+    }
+
+    /// Split the table into ranges of its key, one per connection allowed by the table
+    /// parallelism. Empty when the table is read in one go without a key.
+    ///
+    /// Ranges are cut evenly between the lowest and highest key, so only integer keys are
+    /// handled: anything else is read in one go as before. A checkpointed table is read by
+    /// key even without parallelism, so it can resume where it stopped.
+    ///
+    /// Also tells whether the ranges can resume from their last committed key, which takes
+    /// a checkpoint and a key unique and NOT NULL on its own: rows sharing the committed key
+    /// would be skipped, and rows with a NULL one never committed. Without it, an interrupted
+    /// table is copied again in full.
+    async fn plan_ranges(
+        &self,
+        conn: &mut sqlx::MySqlConnection,
+    ) -> Result<(Vec<KeyRange>, bool), ExtractorError> {
+        let parallelism = self.migrate_table_config.parallelism;
+
+        if parallelism <= 1 && self.checkpoint.is_none() {
+            return Ok((Vec::new(), false));
+        }
+
+        // Both apply to the table as a whole: a range of it cannot honor them on its own.
+        if self.migrate_table_config.limit.is_some() || self.migrate_table_config.order_by.is_some()
+        {
+            return Ok((Vec::new(), false));
+        }
+
+        let key = match &self.migrate_table_config.chunk_key {
+            Some(key) => key.clone(),
+            // For a composite primary key its leading column is still served by the index.
+            None => match sqlx::query(SELECT_PRIMARY_KEY)
+                .bind(&self.name)
+                .fetch_optional(&mut *conn)
+                .await?
+            {
+                Some(row) => row.get::<String, usize>(0),
+                None => {
+                    tracing::warn!(
+                        "table {} has no primary key to read it by, reading it in one go",
+                        self.name
+                    );
+
+                    return Ok((Vec::new(), false));
+                }
+            },
+        };
+
+        // Rows past the committed key are deleted from the target on resume, by the values of
+        // the source: a transformed key holds others there. Rows read by several subset
+        // queries do not come in key order.
+        let transformed = self.migrate_table_config.transformers.contains_key(&key);
+        let split = self.migrate_table_config.subset.len() > 1;
+        let resumable = self.checkpoint.is_some()
+            && !transformed
+            && !split
+            && sqlx::query(SELECT_UNIQUE_KEY)
+                .bind(&self.name)
+                .bind(&key)
+                .fetch_one(&mut *conn)
+                .await?
+                .try_get::<i64, usize>(0)?
+                > 0;
+
+        if self.checkpoint.is_some() && !resumable {
+            if transformed {
+                tracing::warn!(
+                    "key {} of table {} is transformed, an interrupted copy of the table starts over",
+                    key,
+                    self.name
+                );
+            } else if split {
+                tracing::warn!(
+                    "table {} is read by several subset queries, an interrupted copy of the table starts over",
+                    self.name
+                );
+            } else {
+                tracing::warn!(
+                    "key {} of table {} is not unique and NOT NULL on its own, an interrupted copy of the table starts over",
+                    key,
+                    self.name
+                );
             }
 
-            rows.push(values);
+            if parallelism <= 1 {
+                return Ok((Vec::new(), false));
+            }
+        }
 
-            if rows.len() == batch_size {
-                let length = rows.len();
-                let old_rows = std::mem::replace(&mut rows, Vec::with_capacity(batch_size));
+        // Bounds of the rows matching the filter, so the ranges get an even share of them. The
+        // subset conditions are left out, they may take several queries.
+        let mut bounds_query =
+            format!("SELECT MIN(`{}`), MAX(`{}`) FROM `{}`", key, key, self.name);
+        if let Some(where_clause) = &self.migrate_table_config.where_clause {
+            bounds_query.push_str(&format!(" WHERE {}", where_clause));
+        }
+        let bounds = conn.fetch_one(AssertSqlSafe(bounds_query)).await?;
+        let min = MysqlValueDecoded::try_from(ValueRef::to_owned(&bounds.try_get_raw(0)?))?;
+        let max = MysqlValueDecoded::try_from(ValueRef::to_owned(&bounds.try_get_raw(1)?))?;
 
-                // acquire a new connection for batch so we can insert in parallel with fetching data
-                let mut conn = self.target_pool.acquire().await?;
-                let name = self.name.clone();
+        let whole_table = vec![KeyRange {
+            key: key.clone(),
+            lower: None,
+            upper: None,
+            committed: None,
+        }];
 
-                tokio::spawn(async move {
-                    if let Err(e) = insert_batch(
-                        name.as_str(),
-                        conn.deref_mut(),
-                        old_rows,
-                        batch_id,
-                        strategy,
-                    )
-                    .await
-                    {
-                        tracing::error!("Failed to insert batch for table {}: {}", name, e);
+        let (min, max) = match (min.as_i128(), max.as_i128()) {
+            (Some(min), Some(max)) => (min, max),
+            // Empty table
+            _ if matches!(min, MysqlValueDecoded::Null) => return Ok((whole_table, resumable)),
+            _ => {
+                tracing::warn!(
+                    "key {} of table {} is not an integer ({:?}), reading it in one go",
+                    key,
+                    self.name,
+                    min
+                );
+
+                return Ok((Vec::new(), false));
+            }
+        };
+
+        let span = max - min + 1;
+        let chunks = (parallelism.max(1) as i128).min(span);
+        let step = (span + chunks - 1) / chunks;
+        let boundaries = (1..chunks)
+            .map(|i| min + i * step)
+            .filter(|boundary| *boundary <= max)
+            .collect::<Vec<_>>();
+
+        if boundaries.is_empty() {
+            return Ok((whole_table, resumable));
+        }
+
+        // The outer ranges are left open so rows outside [min, max] are never lost.
+        let lowers = std::iter::once(None).chain(boundaries.iter().copied().map(Some));
+        let uppers = boundaries
+            .iter()
+            .copied()
+            .map(Some)
+            .chain(std::iter::once(None));
+
+        let ranges = lowers
+            .zip(uppers)
+            .map(|(lower, upper)| KeyRange {
+                key: key.clone(),
+                lower,
+                upper,
+                committed: None,
+            })
+            .collect();
+
+        Ok((ranges, resumable))
+    }
+
+    /// Stream the rows of select queries into the target, one query after the other, in
+    /// batches.
+    ///
+    /// Batches are inserted by tasks of their own while the next rows are read, and are
+    /// all awaited before returning. Once a batch failed, or another range of the table
+    /// did, no more rows are read.
+    async fn copy_rows(
+        &self,
+        source_conn: &mut sqlx::MySqlConnection,
+        select_queries: Vec<String>,
+        commits: Option<(usize, RangeCommits)>,
+        shared: &CopyShared<'_>,
+    ) -> Result<(), ExtractorError> {
+        let mut batches = JoinSet::new();
+        let mut batch_ids_by_task = HashMap::new();
+        let mut failed_batches = Vec::new();
+
+        let read = async {
+            let batch_size = self.migrate_table_config.batch_size;
+            let batch_max_bytes = self.migrate_table_config.batch_max_bytes;
+            let mut rows = Vec::with_capacity(batch_size);
+            let mut rows_bytes = 0;
+            let mut sequence = 0;
+            // Read before transformers run, the checkpoint needs the source key.
+            let mut last_key = None;
+
+            for select_query in select_queries {
+                let mut select_stream = source_conn.fetch(AssertSqlSafe(select_query));
+
+                loop {
+                    let row = if shared.cancelled.load(Ordering::SeqCst) {
+                        None
+                    } else {
+                        select_stream.try_next().await?
+                    };
+
+                    let Some(row) = row else {
+                        break;
+                    };
+
+                    let mut values = Vec::with_capacity(row.len());
+
+                    for i in 0..row.len() {
+                        let value = ValueRef::to_owned(&row.try_get_raw(i)?);
+                        values.push(MysqlValueDecoded::try_from(value)?);
                     }
-                });
 
-                batch_id += 1;
-                progress_bar.inc(length as u64);
+                    if let Some((key_index, _)) = &commits {
+                        last_key = values.get(*key_index).and_then(MysqlValueDecoded::as_i128);
+                    }
+
+                    let mut values = transform_row(
+                        &self.migrate_table_config.transformers,
+                        shared.indexed_fields,
+                        &shared.transform_order,
+                        &shared.samples,
+                        values,
+                    );
+                    shared.unique_keys.ensure(&mut values);
+
+                    rows_bytes += values.iter().map(MysqlValueDecoded::size).sum::<usize>();
+                    rows.push(values);
+
+                    if rows.len() == batch_size || rows_bytes >= batch_max_bytes {
+                        let old_rows = std::mem::replace(&mut rows, Vec::with_capacity(batch_size));
+                        rows_bytes = 0;
+                        let commit = commits
+                            .as_ref()
+                            .map(|(_, commits)| (commits.clone(), sequence, last_key));
+                        sequence += 1;
+
+                        self.spawn_batch(
+                            &mut batches,
+                            &mut batch_ids_by_task,
+                            old_rows,
+                            commit,
+                            shared,
+                        )
+                        .await?;
+
+                        while let Some(result) = batches.try_join_next_with_id() {
+                            record_batch(result, &mut batch_ids_by_task, &mut failed_batches);
+                        }
+
+                        if !failed_batches.is_empty() {
+                            shared.cancelled.store(true, Ordering::SeqCst);
+                        }
+                    }
+                }
             }
+
+            if !rows.is_empty() && !shared.cancelled.load(Ordering::SeqCst) {
+                let commit = commits
+                    .as_ref()
+                    .map(|(_, commits)| (commits.clone(), sequence, last_key));
+
+                self.spawn_batch(&mut batches, &mut batch_ids_by_task, rows, commit, shared)
+                    .await?;
+            }
+
+            Ok::<(), ExtractorError>(())
         }
+        .await;
 
-        if !rows.is_empty() {
-            let length = rows.len();
-            let mut conn = self.target_pool.acquire().await?;
+        // Whatever happened while reading, never leave inserts running behind our back.
+        while let Some(result) = batches.join_next_with_id().await {
+            record_batch(result, &mut batch_ids_by_task, &mut failed_batches);
+        }
 
-            insert_batch(
-                self.name.as_str(),
-                conn.deref_mut(),
-                rows,
-                batch_id,
-                strategy,
-            )
-            .await?;
-            progress_bar.inc(length as u64);
+        if let Err(err) = read {
+            shared.cancelled.store(true, Ordering::SeqCst);
+
+            return Err(err);
+        }
+
+        if !failed_batches.is_empty() {
+            shared.cancelled.store(true, Ordering::SeqCst);
+
+            return Err(ExtractorError::BatchFailed(failed_batches));
         }
 
         Ok(())
     }
+
+    /// Insert a batch on a connection of its own, so it runs in parallel with fetching the
+    /// next rows. The progress bar and the checkpoint only move once it is committed.
+    ///
+    /// Waits first for one of the table in flight batches to finish when they are all
+    /// taken, which holds the reading back while the target catches up.
+    async fn spawn_batch(
+        &self,
+        batches: &mut JoinSet<Result<(), BatchFailure>>,
+        batch_ids_by_task: &mut HashMap<tokio::task::Id, u32>,
+        rows: Vec<Vec<MysqlValueDecoded>>,
+        commit: Option<(RangeCommits, u64, Option<i128>)>,
+        shared: &CopyShared<'_>,
+    ) -> Result<(), ExtractorError> {
+        let length = rows.len();
+        let batch_id = shared.batch_ids.fetch_add(1, Ordering::SeqCst);
+        let strategy = self.migrate_table_config.load_strategy;
+        let permit = shared.in_flight.clone().acquire_owned().await.unwrap();
+
+        // acquire a new connection for batch so we can insert in parallel with fetching data
+        let conn = self.target_pool.acquire().await?;
+        let target_pool = self.target_pool.clone();
+        let name = self.name.clone();
+        let progress_bar = shared.progress_bar.clone();
+
+        let task = batches.spawn(async move {
+            insert_batch_splitting(&name, &target_pool, conn, rows, batch_id, strategy)
+                .await
+                .map_err(|e| BatchFailure {
+                    batch_id,
+                    error: e.to_string(),
+                })?;
+            drop(permit);
+
+            progress_bar.inc(length as u64);
+
+            if let Some((commits, sequence, last_key)) = commit {
+                commits.committed(sequence, last_key);
+            }
+
+            Ok(())
+        });
+        batch_ids_by_task.insert(task.id(), batch_id);
+
+        Ok(())
+    }
+}
+
+/// Characters (bytes for binary strings) a column of this type holds, for the types whose
+/// length is declared.
+fn max_length(column_type: &str) -> Option<usize> {
+    let (name, rest) = column_type.split_once('(')?;
+
+    match name {
+        "char" | "varchar" | "binary" | "varbinary" => rest.split_once(')')?.0.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Select queries read one after the other on a connection, with the range of the key they
+/// cover and its index.
+type Chunk = (Vec<String>, Option<(usize, KeyRange)>);
+
+/// State shared by the ranges of a table copied at once.
+struct CopyShared<'a> {
+    indexed_fields: &'a [String],
+    /// Columns with transformers, in the order to transform them.
+    transform_order: Vec<usize>,
+    /// Keys of the unique indexes taken so far, by the rows of every range.
+    unique_keys: UniqueKeys,
+    samples: ColumnSamples,
+    /// Batch ids name the LOAD DATA temporary files, they must be unique in the table.
+    batch_ids: AtomicU32,
+    /// Set as soon as a range failed, the others then stop reading.
+    cancelled: AtomicBool,
+    /// Batches read but not inserted yet, capped as they hold their rows in memory.
+    in_flight: Arc<Semaphore>,
+    progress_bar: &'a ProgressBar,
+}
+
+/// Record the outcome of a finished batch task, a panic counting as a failure as well.
+fn record_batch(
+    result: Result<(tokio::task::Id, Result<(), BatchFailure>), JoinError>,
+    batch_ids_by_task: &mut HashMap<tokio::task::Id, u32>,
+    failed_batches: &mut Vec<BatchFailure>,
+) {
+    match result {
+        Ok((id, outcome)) => {
+            batch_ids_by_task.remove(&id);
+
+            if let Err(failure) = outcome {
+                tracing::error!(
+                    "failed to insert batch {}: {}",
+                    failure.batch_id,
+                    failure.error
+                );
+                failed_batches.push(failure);
+            }
+        }
+        Err(err) => {
+            let batch_id = batch_ids_by_task.remove(&err.id()).unwrap_or_default();
+            tracing::error!("batch {} task failed: {}", batch_id, err);
+
+            failed_batches.push(BatchFailure {
+                batch_id,
+                error: err.to_string(),
+            });
+        }
+    }
+}
+
+/// Insert a batch, halving it when the target rejects it for exceeding
+/// `max_allowed_packet` until the halves fit. A single row too large fails as usual.
+async fn insert_batch_splitting(
+    name: &str,
+    target_pool: &MySqlPool,
+    mut conn: PoolConnection<MySql>,
+    rows: Vec<Vec<MysqlValueDecoded>>,
+    batch_id: u32,
+    strategy: LoadStrategy,
+) -> Result<(), sqlx::Error> {
+    // Last in first out: the first half of a split batch is pushed last to go first.
+    let mut pending = vec![rows];
+
+    while let Some(mut rows) = pending.pop() {
+        match insert_batch(name, conn.deref_mut(), &rows, batch_id, strategy).await {
+            Ok(()) => (),
+            Err(e) if is_packet_too_large(&e) && rows.len() > 1 => {
+                tracing::trace!(
+                    "[{} - {}] {} rows are too large for the target, splitting them",
+                    name,
+                    batch_id,
+                    rows.len()
+                );
+
+                // The server closes the connection after rejecting a packet.
+                conn.close_on_drop();
+                conn = target_pool.acquire().await?;
+
+                let second_half = rows.split_off(rows.len() / 2);
+                pending.push(second_half);
+                pending.push(rows);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Only the server saying so counts: a connection lost midway may as well be a network
+/// failure, or come after the rows were written, and sending them again would hide it or
+/// duplicate them.
+fn is_packet_too_large(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Database(err) => {
+            err.try_downcast_ref::<MySqlDatabaseError>()
+                .is_some_and(|err| {
+                    matches!(err.number(), ER_NET_PACKET_TOO_LARGE | ER_TOO_LONG_STRING)
+                })
+        }
+        _ => false,
+    }
 }
 
 async fn insert_batch(
     name: &str,
     conn: &mut sqlx::MySqlConnection,
-    rows: Vec<Vec<MysqlValueDecoded>>,
+    rows: &[Vec<MysqlValueDecoded>],
     batch_id: u32,
     strategy: LoadStrategy,
 ) -> Result<(), sqlx::Error> {
@@ -178,7 +998,7 @@ async fn insert_batch(
 async fn insert_batch_insert(
     name: &str,
     conn: &mut sqlx::MySqlConnection,
-    rows: Vec<Vec<MysqlValueDecoded>>,
+    rows: &[Vec<MysqlValueDecoded>],
     batch_id: u32,
 ) -> Result<(), sqlx::Error> {
     let mut query_builder = QueryBuilder::new(format!("INSERT INTO `{}`", name));
@@ -188,31 +1008,31 @@ async fn insert_batch_insert(
         for value in new_category {
             match value {
                 MysqlValueDecoded::Int(i) => {
-                    b.push_bind(i);
+                    b.push_bind(*i);
                 }
                 MysqlValueDecoded::UInt(u) => {
-                    b.push_bind(u);
+                    b.push_bind(*u);
                 }
                 MysqlValueDecoded::Double(f) => {
-                    b.push_bind(f);
+                    b.push_bind(*f);
                 }
                 MysqlValueDecoded::Decimal(f) => {
-                    b.push_bind(f);
+                    b.push_bind(*f);
                 }
                 MysqlValueDecoded::String(s) => {
-                    b.push_bind(s);
+                    b.push_bind(s.as_str());
                 }
                 MysqlValueDecoded::DateTime(dt) => {
-                    b.push_bind(dt);
+                    b.push_bind(*dt);
                 }
                 MysqlValueDecoded::Bytes(bytes) => {
-                    b.push_bind(bytes);
+                    b.push_bind(bytes.as_slice());
                 }
                 MysqlValueDecoded::Null => {
                     b.push_bind(None::<i32>);
                 }
                 MysqlValueDecoded::Bool(bool) => {
-                    b.push_bind(bool);
+                    b.push_bind(*bool);
                 }
             }
         }
@@ -239,7 +1059,7 @@ async fn insert_batch_insert(
 async fn insert_batch_load_data(
     name: &str,
     conn: &mut sqlx::MySqlConnection,
-    rows: Vec<Vec<MysqlValueDecoded>>,
+    rows: &[Vec<MysqlValueDecoded>],
     batch_id: u32,
 ) -> Result<(), sqlx::Error> {
     use std::io::BufWriter;
@@ -252,7 +1072,7 @@ async fn insert_batch_load_data(
 
     // Create a temporary file
     let temp_file_path = format!("/tmp/mysql_migrate_{}_{}.csv", name, batch_id);
-    let file = std::fs::File::create(&temp_file_path).map_err(|e| sqlx::Error::Io(e))?;
+    let file = std::fs::File::create(&temp_file_path).map_err(sqlx::Error::Io)?;
     let mut writer = BufWriter::new(file);
 
     // Write rows to CSV file
@@ -260,22 +1080,22 @@ async fn insert_batch_load_data(
         let mut first = true;
         for value in row {
             if !first {
-                writer.write_all(b"\t").map_err(|e| sqlx::Error::Io(e))?;
+                writer.write_all(b"\t").map_err(sqlx::Error::Io)?;
             }
             first = false;
 
             match value {
                 MysqlValueDecoded::Int(i) => {
-                    write!(writer, "{}", i).map_err(|e| sqlx::Error::Io(e))?;
+                    write!(writer, "{}", i).map_err(sqlx::Error::Io)?;
                 }
                 MysqlValueDecoded::UInt(u) => {
-                    write!(writer, "{}", u).map_err(|e| sqlx::Error::Io(e))?;
+                    write!(writer, "{}", u).map_err(sqlx::Error::Io)?;
                 }
                 MysqlValueDecoded::Double(f) => {
-                    write!(writer, "{}", f).map_err(|e| sqlx::Error::Io(e))?;
+                    write!(writer, "{}", f).map_err(sqlx::Error::Io)?;
                 }
                 MysqlValueDecoded::Decimal(d) => {
-                    write!(writer, "{}", d).map_err(|e| sqlx::Error::Io(e))?;
+                    write!(writer, "{}", d).map_err(sqlx::Error::Io)?;
                 }
                 MysqlValueDecoded::String(s) => {
                     // Escape special characters for MySQL LOAD DATA
@@ -286,16 +1106,16 @@ async fn insert_batch_load_data(
                         .replace("\r", "\\r")
                         .replace("\0", "\\0");
 
-                    write!(writer, "{}", escaped).map_err(|e| sqlx::Error::Io(e))?;
+                    write!(writer, "{}", escaped).map_err(sqlx::Error::Io)?;
                 }
                 MysqlValueDecoded::DateTime(dt) => {
-                    write!(writer, "{}", dt).map_err(|e| sqlx::Error::Io(e))?;
+                    write!(writer, "{}", dt).map_err(sqlx::Error::Io)?;
                 }
                 MysqlValueDecoded::Bytes(bytes) => {
                     // Write raw bytes, escaped for MySQL LOAD DATA. A hex literal would not do:
                     // LOAD DATA stores `0x4142` as that literal text, not as the bytes it spells.
                     for byte in bytes {
-                        match byte {
+                        match *byte {
                             b'\\' => writer.write_all(b"\\\\"),
                             b'\t' => writer.write_all(b"\\t"),
                             b'\n' => writer.write_all(b"\\n"),
@@ -303,22 +1123,22 @@ async fn insert_batch_load_data(
                             0 => writer.write_all(b"\\0"),
                             b => writer.write_all(&[b]),
                         }
-                        .map_err(|e| sqlx::Error::Io(e))?;
+                        .map_err(sqlx::Error::Io)?;
                     }
                 }
                 MysqlValueDecoded::Null => {
-                    writer.write_all(b"\\N").map_err(|e| sqlx::Error::Io(e))?;
+                    writer.write_all(b"\\N").map_err(sqlx::Error::Io)?;
                 }
                 MysqlValueDecoded::Bool(b) => {
-                    write!(writer, "{}", if b { 1 } else { 0 }).map_err(|e| sqlx::Error::Io(e))?;
+                    write!(writer, "{}", if *b { 1 } else { 0 }).map_err(sqlx::Error::Io)?;
                 }
             }
         }
-        writer.write_all(b"\n").map_err(|e| sqlx::Error::Io(e))?;
+        writer.write_all(b"\n").map_err(sqlx::Error::Io)?;
     }
 
     // Flush the writer
-    writer.flush().map_err(|e| sqlx::Error::Io(e))?;
+    writer.flush().map_err(sqlx::Error::Io)?;
     drop(writer);
 
     tracing::trace!(
@@ -344,3 +1164,61 @@ async fn insert_batch_load_data(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(lower: Option<i128>, upper: Option<i128>, committed: Option<i128>) -> KeyRange {
+        KeyRange {
+            key: "id".to_string(),
+            lower,
+            upper,
+            committed,
+        }
+    }
+
+    #[test]
+    fn whole_table_has_no_condition() {
+        assert_eq!(range(None, None, None).condition(), None);
+    }
+
+    #[test]
+    fn first_range_reads_null_keys() {
+        assert_eq!(
+            range(None, Some(100), None).condition().as_deref(),
+            Some("(`id` < 100 OR `id` IS NULL)")
+        );
+    }
+
+    #[test]
+    fn bounded_ranges_include_lower_and_exclude_upper() {
+        assert_eq!(
+            range(Some(100), Some(200), None).condition().as_deref(),
+            Some("`id` >= 100 AND `id` < 200")
+        );
+        assert_eq!(
+            range(Some(200), None, None).condition().as_deref(),
+            Some("`id` >= 200")
+        );
+    }
+
+    #[test]
+    fn committed_rows_are_not_read_again() {
+        assert_eq!(
+            range(Some(100), Some(200), Some(150))
+                .condition()
+                .as_deref(),
+            Some("`id` > 150 AND `id` < 200")
+        );
+        // NULL keys come first, so they were committed with the first rows.
+        assert_eq!(
+            range(None, Some(100), Some(50)).condition().as_deref(),
+            Some("`id` > 50 AND `id` < 100")
+        );
+        assert_eq!(
+            range(None, None, Some(50)).condition().as_deref(),
+            Some("`id` > 50")
+        );
+    }
+}
@@ -1,10 +1,14 @@
+mod checkpoint;
 mod config;
 mod extractor;
+mod snapshot;
+mod subset;
 mod transformer;
 mod value;
 
+use crate::checkpoint::{Checkpoint, Phase};
 use crate::config::{Config, CreateConfig, DatabaseConfig};
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use indicatif::{FormattedDuration, MultiProgress, ProgressBar, ProgressStyle};
 use sqlx::mysql::{MySqlConnectOptions, MySqlPoolOptions};
 use sqlx::{AssertSqlSafe, Row};
@@ -12,7 +16,7 @@ use sqlx::{ConnectOptions, Executor};
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::str::FromStr;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use tracing_indicatif::IndicatifLayer;
@@ -22,11 +26,21 @@ use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
 #[derive(Parser)]
+#[clap(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 pub struct Args {
-    config: PathBuf,
+    #[clap(subcommand)]
+    command: Option<Command>,
+    #[clap(required = true)]
+    config: Option<PathBuf>,
     /// Enable debug logging (shows trace level logs)
     #[clap(short, long)]
     debug: bool,
+    /// Record in this file how far each table got, so an interrupted migration can be resumed
+    #[clap(long)]
+    checkpoint: Option<PathBuf>,
+    /// Resume the migration recorded in the checkpoint file instead of starting over
+    #[clap(long, requires = "checkpoint")]
+    resume: bool,
 }
 
 /// Shared view of the migration used to derive the overall remaining time. indicatif alone
@@ -123,11 +137,80 @@ fn finish_table(summary_bar: &ProgressBar, progress: &MigrationProgress, name: &
     ));
 }
 
+#[derive(Subcommand)]
+enum Command {
+    /// Decrypt values encrypted by the encrypt transformer, given as arguments or one per line
+    /// on the standard input
+    Decrypt {
+        /// File holding the key, as 64 hexadecimal characters
+        #[clap(long, required_unless_present = "key_env")]
+        key_file: Option<PathBuf>,
+        /// Environment variable holding the key, as 64 hexadecimal characters
+        #[clap(long, conflicts_with = "key_file")]
+        key_env: Option<String>,
+        /// Alphabet the values were encrypted with
+        #[clap(long, value_enum, default_value_t)]
+        alphabet: transformer::Alphabet,
+        values: Vec<String>,
+    },
+}
+
+/// Print the decrypted values one per line, in the order they are given.
+fn decrypt(
+    key_file: Option<PathBuf>,
+    key_env: Option<String>,
+    alphabet: transformer::Alphabet,
+    values: Vec<String>,
+) {
+    let cipher = transformer::load_key(key_file.as_deref(), key_env.as_deref())
+        .and_then(|key| transformer::Cipher::new(&key, alphabet));
+    let cipher = match cipher {
+        Ok(cipher) => cipher,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let values: Box<dyn Iterator<Item = String>> = if values.is_empty() {
+        Box::new(std::io::stdin().lines().map_while(Result::ok))
+    } else {
+        Box::new(values.into_iter())
+    };
+
+    let mut failed = false;
+    for value in values {
+        match cipher.decrypt(&value) {
+            Ok(decrypted) => println!("{}", decrypted),
+            Err(e) => {
+                eprintln!("cannot decrypt {:?}: {}", value, e);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
+
+    if let Some(Command::Decrypt {
+        key_file,
+        key_env,
+        alphabet,
+        values,
+    }) = args.command
+    {
+        return decrypt(key_file, key_env, alphabet, values);
+    }
+
+    let config_path = args.config.expect("the configuration is required");
     let config: Config =
-        serde_yaml::from_reader(std::fs::File::open(args.config).unwrap()).unwrap();
+        serde_yaml::from_reader(std::fs::File::open(config_path).unwrap()).unwrap();
 
     let indicatif_layer = IndicatifLayer::new();
 
@@ -147,6 +230,15 @@ async fn main() {
         .with(indicatif_layer)
         .init();
 
+    let problems = config.migrate.problems();
+    if !problems.is_empty() {
+        for problem in problems {
+            tracing::error!("invalid configuration for {}", problem);
+        }
+
+        std::process::exit(1);
+    }
+
     let source_connect_options = MySqlConnectOptions::from_str(config.source.dsn.as_str())
         .unwrap()
         .disable_statement_logging();
@@ -154,14 +246,41 @@ async fn main() {
         .unwrap()
         .disable_statement_logging();
 
-    let source_pool = match MySqlPoolOptions::new()
+    let mut source_pool_options = MySqlPoolOptions::new()
         .max_connections(config.source.max_connections)
         .test_before_acquire(true)
         // sqlx defaults to 30s, which a table queued behind a long running one blows through
         // easily. Table concurrency is capped to the pool size below, so reaching this timeout
         // now means something is genuinely stuck rather than merely busy.
-        .acquire_timeout(Duration::from_secs(600))
-        .connect_with(source_connect_options)
+        .acquire_timeout(Duration::from_secs(600));
+
+    // Only the connections opened when the snapshot is taken are inside it: the pool must
+    // keep them for the whole run, and any connection opened afterwards would silently read
+    // newer data, so it is refused instead.
+    let snapshot_taken = Arc::new(AtomicBool::new(false));
+    if config.source.consistent_snapshot {
+        let snapshot_taken = snapshot_taken.clone();
+
+        source_pool_options = source_pool_options
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .after_connect(move |_conn, _meta| {
+                let taken = snapshot_taken.load(Ordering::SeqCst);
+
+                Box::pin(async move {
+                    if taken {
+                        return Err(sqlx::Error::Protocol(
+                            "source connection lost after the consistent snapshot was taken, a new one would not see the same data".to_string(),
+                        ));
+                    }
+
+                    Ok(())
+                })
+            });
+    }
+
+    let source_pool = match source_pool_options
+        .connect_with(source_connect_options.clone())
         .await
     {
         Ok(pool) => Arc::new(pool),
@@ -172,6 +291,29 @@ async fn main() {
         }
     };
 
+    if config.source.consistent_snapshot {
+        match snapshot::start_consistent_snapshot(
+            source_pool.as_ref(),
+            &source_connect_options,
+            config.source.max_connections,
+        )
+        .await
+        {
+            Ok(Some(position)) => println!("consistent snapshot taken at {}", position),
+            Ok(None) => println!("consistent snapshot taken, binary logging is disabled on source"),
+            Err(e) => {
+                tracing::error!(
+                    "failed to take consistent snapshot of source database: {}",
+                    e
+                );
+
+                return;
+            }
+        }
+
+        snapshot_taken.store(true, Ordering::SeqCst);
+    }
+
     // get source database charset
     // SELECT default_character_set_name FROM information_schema.SCHEMATA
     // WHERE schema_name = "mydatabasename";
@@ -182,7 +324,43 @@ async fn main() {
         .unwrap();
     let charset = charset_row.get::<&str, usize>(0).to_string();
 
-    prepare_target_database(&config.target, &config.create, charset).await;
+    // Before the target is touched, so a configuration error does not drop it for nothing.
+    match transformer::validate_transformers(source_pool.as_ref(), &config.migrate).await {
+        Ok(problems) if problems.is_empty() => {}
+        Ok(problems) => {
+            for problem in problems {
+                tracing::error!("invalid transformer for {}", problem);
+            }
+
+            std::process::exit(1);
+        }
+        Err(e) => {
+            tracing::error!("failed to read source columns: {}", e);
+
+            return;
+        }
+    }
+
+    let checkpoint = match args.checkpoint {
+        Some(path) if args.resume => match Checkpoint::load(path) {
+            Ok(checkpoint) => Some(Arc::new(checkpoint)),
+            Err(e) => {
+                tracing::error!("failed to resume migration: {}", e);
+
+                return;
+            }
+        },
+        Some(path) => Some(Arc::new(Checkpoint::new(path))),
+        None => None,
+    };
+
+    // Resuming continues in the database the previous run filled, it must not be recreated.
+    let mut create = config.create.clone();
+    if args.resume {
+        create.drop_if_exists = false;
+    }
+
+    prepare_target_database(&config.target, &create, charset).await;
 
     let target_pool = match MySqlPoolOptions::new()
         .max_connections(config.target.max_connections)
@@ -225,7 +403,31 @@ async fn main() {
         .await
         .unwrap();
 
+    // Conditions restricting the tables to the subset, read by their extractor below.
+    let subset_conditions = match &config.subset {
+        Some(subset) => match subset::subset_conditions(source_pool.as_ref(), subset).await {
+            Ok(conditions) => conditions,
+            Err(e) => {
+                tracing::error!("failed to compute subset: {}", e);
+
+                return;
+            }
+        },
+        None => HashMap::new(),
+    };
+
+    let problems = config.migrate.subset_problems(subset_conditions.keys());
+    if !problems.is_empty() {
+        for problem in problems {
+            tracing::error!("invalid configuration for {}", problem);
+        }
+
+        std::process::exit(1);
+    }
+
     let mut handles = Vec::new();
+    // Tables which did not make it, with the reason, reported once everything is done.
+    let failures = Arc::new(Mutex::new(Vec::<(String, String)>::new()));
 
     let multi_progress = MultiProgress::new();
     let sty = ProgressStyle::with_template(
@@ -268,12 +470,20 @@ async fn main() {
 
     for table in tables {
         let name = table.try_get::<&str, usize>(0).unwrap().to_string();
-        let migrate_config = config
+        let mut migrate_config = config
             .migrate
             .tables
             .get(name.as_str())
             .cloned()
             .unwrap_or_default();
+        if let Some(conditions) = subset_conditions.get(name.as_str()) {
+            migrate_config.subset = conditions.clone();
+        }
+        // A table reading with several connections takes as many slots, never more than
+        // there are, or it would wait forever for connections held by no one else.
+        migrate_config.parallelism = migrate_config
+            .parallelism
+            .clamp(1, config.source.max_connections.max(1) as usize);
         let source_pool = source_pool.clone();
         let target_pool = target_pool.clone();
         let table_slots = table_slots.clone();
@@ -281,10 +491,23 @@ async fn main() {
         let summary_bar = summary_bar.clone();
         let progress = progress.clone();
         let sty = sty.clone();
+        let checkpoint = checkpoint.clone();
+        let failures = failures.clone();
 
         let handle = tokio::task::spawn(async move {
+            if let Some(checkpoint) = &checkpoint
+                && checkpoint.table(&name).phase == Some(Phase::Data)
+            {
+                finish_table(&summary_bar, &progress, &name);
+
+                return;
+            }
+
             // Held until the table is done, so it covers both the count and the extraction.
-            let _slot = table_slots.acquire().await.unwrap();
+            let _slot = table_slots
+                .acquire_many(migrate_config.parallelism as u32)
+                .await
+                .unwrap();
 
             // The bar only appears once the table actually starts, so queued tables stay off
             // screen. Inserted one from the back to stay above the summary line.
@@ -296,35 +519,41 @@ async fn main() {
                 .unwrap()
                 .insert(name.clone(), progress_bar.clone());
 
+            let mut exporter = extractor::TableExtractor::new(
+                source_pool,
+                target_pool,
+                migrate_config,
+                name.clone(),
+                checkpoint,
+            );
+
             // Counted here rather than up front: doing it in the loop would need a source
             // connection while every other table is holding one.
-            let count = match sqlx::query(AssertSqlSafe(format!("SELECT COUNT(*) FROM `{}`", name)))
-                .fetch_one(source_pool.as_ref())
-                .await
-            {
-                Ok(row) => row.get::<i64, usize>(0),
+            let count = match exporter.count().await {
+                Ok(count) => count,
                 Err(err) => {
                     progress_bar
                         .abandon_with_message(format!("table {} count failed: {}", name, err));
+                    failures
+                        .lock()
+                        .unwrap()
+                        .push((name.clone(), format!("count failed: {}", err)));
                     finish_table(&summary_bar, &progress, &name);
 
                     return;
                 }
             };
-            progress_bar.set_length(count as u64);
-
-            let mut exporter = extractor::TableExtractor::new(
-                source_pool,
-                target_pool,
-                migrate_config,
-                name.clone(),
-            );
+            progress_bar.set_length(count);
 
             match exporter.extract(&progress_bar).await {
                 Ok(_) => (),
                 Err(err) => {
                     progress_bar
                         .abandon_with_message(format!("table {} backup failed: {}", name, err));
+                    failures
+                        .lock()
+                        .unwrap()
+                        .push((name.clone(), format!("backup failed: {}", err)));
                 }
             }
 
@@ -337,6 +566,17 @@ async fn main() {
     for handle in handles {
         handle.await.unwrap();
     }
+
+    let failures = failures.lock().unwrap();
+    if !failures.is_empty() {
+        eprintln!("{} table(s) failed:", failures.len());
+
+        for (name, reason) in failures.iter() {
+            eprintln!("  {}: {}", name, reason);
+        }
+
+        std::process::exit(1);
+    }
 }
 
 async fn prepare_target_database(target: &DatabaseConfig, create: &CreateConfig, charset: String) {
@@ -0,0 +1,89 @@
+use sqlx::mysql::{MySqlConnectOptions, MySqlConnection};
+use sqlx::{ConnectOptions, Connection, Executor, MySqlPool, Row};
+use std::fmt;
+
+/// Where the source binary log stood when the snapshot was taken, so a replica or a CDC
+/// pipeline can pick up from the exact point the migrated data reflects.
+pub(crate) struct BinlogPosition {
+    file: String,
+    position: u64,
+    gtid_executed: Option<String>,
+}
+
+impl fmt::Display for BinlogPosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "binlog file {} position {}", self.file, self.position)?;
+
+        if let Some(gtid_executed) = &self.gtid_executed {
+            write!(f, ", gtid set {}", gtid_executed)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Put every connection of the source pool inside one shared point in time, the way
+/// `mysqldump --single-transaction --source-data` does.
+///
+/// All the connections are opened and held first, then a global read lock freezes writes
+/// just long enough for each of them to start its consistent snapshot transaction and for
+/// the binary log position to be read. Once released to the pool, any table read on any of
+/// these connections sees the database as it was at that instant.
+///
+/// Returns None when binary logging is disabled on the source.
+pub(crate) async fn start_consistent_snapshot(
+    pool: &MySqlPool,
+    connect_options: &MySqlConnectOptions,
+    connections: u32,
+) -> Result<Option<BinlogPosition>, sqlx::Error> {
+    let mut pooled = Vec::with_capacity(connections as usize);
+
+    for _ in 0..connections {
+        pooled.push(pool.acquire().await?);
+    }
+
+    // The lock lives on a connection of its own, outside the pool: should anything below
+    // fail, dropping it closes the session and releases the lock with it.
+    let mut lock_conn = connect_options.connect().await?;
+    lock_conn.execute("FLUSH TABLES WITH READ LOCK").await?;
+
+    for conn in pooled.iter_mut() {
+        conn.execute("SET SESSION TRANSACTION ISOLATION LEVEL REPEATABLE READ")
+            .await?;
+        conn.execute("START TRANSACTION WITH CONSISTENT SNAPSHOT, READ ONLY")
+            .await?;
+    }
+
+    let position = binlog_position(&mut lock_conn).await?;
+
+    lock_conn.execute("UNLOCK TABLES").await?;
+    lock_conn.close().await?;
+
+    Ok(position)
+}
+
+async fn binlog_position(
+    conn: &mut MySqlConnection,
+) -> Result<Option<BinlogPosition>, sqlx::Error> {
+    // MySQL 8.4 removed SHOW MASTER STATUS in favor of SHOW BINARY LOG STATUS, which older
+    // servers do not know about.
+    let row = match conn.fetch_optional("SHOW BINARY LOG STATUS").await {
+        Ok(row) => row,
+        Err(sqlx::Error::Database(_)) => conn.fetch_optional("SHOW MASTER STATUS").await?,
+        Err(err) => return Err(err),
+    };
+
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    Ok(Some(BinlogPosition {
+        file: row.try_get::<String, &str>("File")?,
+        position: row.try_get::<u64, &str>("Position")?,
+        // Not there on MariaDB, and empty when GTIDs are off.
+        gtid_executed: row
+            .try_get::<String, &str>("Executed_Gtid_Set")
+            .ok()
+            .filter(|gtid_executed| !gtid_executed.is_empty()),
+    }))
+}
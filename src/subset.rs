@@ -0,0 +1,496 @@
+use crate::config::SubsetConfig;
+use sqlx::{AssertSqlSafe, MySqlPool, Row};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+
+const SELECT_FOREIGN_KEYS: &str = "SELECT `TABLE_NAME`, `CONSTRAINT_NAME`, `COLUMN_NAME`, `REFERENCED_TABLE_NAME`, `REFERENCED_COLUMN_NAME` FROM `INFORMATION_SCHEMA`.`KEY_COLUMN_USAGE` WHERE `TABLE_SCHEMA` = DATABASE() AND `REFERENCED_TABLE_SCHEMA` = DATABASE() AND `REFERENCED_TABLE_NAME` IS NOT NULL ORDER BY `TABLE_NAME`, `CONSTRAINT_NAME`, `ORDINAL_POSITION`";
+const SELECT_PRIMARY_KEYS: &str = "SELECT `TABLE_NAME`, `COLUMN_NAME` FROM `INFORMATION_SCHEMA`.`KEY_COLUMN_USAGE` WHERE `TABLE_SCHEMA` = DATABASE() AND `CONSTRAINT_NAME` = 'PRIMARY' ORDER BY `TABLE_NAME`, `ORDINAL_POSITION`";
+const SELECT_COLUMN_TYPES: &str = "SELECT `TABLE_NAME`, `COLUMN_NAME`, `DATA_TYPE`, `COLUMN_TYPE`, `CHARACTER_SET_NAME`, `COLLATION_NAME` FROM `INFORMATION_SCHEMA`.`COLUMNS` WHERE `TABLE_SCHEMA` = DATABASE()";
+
+/// Size past which a list of values is split over several conditions. Well under the 4 MiB
+/// `max_allowed_packet` MySQL 5.7 defaults to, whatever else the query holds.
+const MAX_CONDITION_BYTES: usize = 1024 * 1024;
+
+/// `table.columns` referencing `referenced_table.referenced_columns`.
+#[derive(Debug)]
+struct ForeignKey {
+    table: String,
+    columns: Vec<String>,
+    referenced_table: String,
+    referenced_columns: Vec<String>,
+}
+
+/// Types of the columns of each table, by table then column.
+type ColumnTypes = HashMap<String, HashMap<String, KeyType>>;
+
+/// How a key value, read as the hexadecimal form of its binary string, is written back in
+/// a condition. A bare `X'..'` literal is a number next to a numeric column (`X'3132'`, the
+/// text "12", is 12594), so it goes through `UNHEX()` and a cast to the type of the column.
+#[derive(Debug)]
+enum KeyType {
+    Signed,
+    Unsigned,
+    /// The `DECIMAL(M,D)` of the column.
+    Decimal(String),
+    Date,
+    DateTime,
+    Time,
+    Text {
+        charset: String,
+        collation: String,
+    },
+    /// Binary strings, and anything else, compared byte for byte.
+    Binary,
+}
+
+impl KeyType {
+    fn new(
+        data_type: &str,
+        column_type: &str,
+        charset: Option<String>,
+        collation: Option<String>,
+    ) -> Self {
+        match (data_type, charset, collation) {
+            ("tinyint" | "smallint" | "mediumint" | "int" | "integer" | "bigint" | "year", ..) => {
+                if column_type.contains("unsigned") {
+                    KeyType::Unsigned
+                } else {
+                    KeyType::Signed
+                }
+            }
+            ("decimal" | "numeric", ..) => {
+                let precision = column_type.split(' ').next().unwrap_or(column_type);
+
+                KeyType::Decimal(precision.to_uppercase())
+            }
+            ("date", ..) => KeyType::Date,
+            ("datetime" | "timestamp", ..) => KeyType::DateTime,
+            ("time", ..) => KeyType::Time,
+            (_, Some(charset), Some(collation)) => KeyType::Text { charset, collation },
+            _ => KeyType::Binary,
+        }
+    }
+
+    fn literal(&self, hex: &str) -> String {
+        match self {
+            KeyType::Signed => format!("CAST(UNHEX('{}') AS SIGNED)", hex),
+            KeyType::Unsigned => format!("CAST(UNHEX('{}') AS UNSIGNED)", hex),
+            KeyType::Decimal(decimal) => format!("CAST(UNHEX('{}') AS {})", hex, decimal),
+            KeyType::Date => format!("CAST(UNHEX('{}') AS DATE)", hex),
+            KeyType::DateTime => format!("CAST(UNHEX('{}') AS DATETIME(6))", hex),
+            KeyType::Time => format!("CAST(UNHEX('{}') AS TIME(6))", hex),
+            KeyType::Text { charset, collation } => format!(
+                "CONVERT(UNHEX('{}') USING {}) COLLATE {}",
+                hex, charset, collation
+            ),
+            KeyType::Binary => format!("X'{}'", hex),
+        }
+    }
+}
+
+/// Rows of a table in the subset: the root condition, or any of the column values collected
+/// by following the foreign keys.
+///
+/// Values are kept as the hexadecimal form of their binary string, so any column type goes
+/// through without quoting concerns, and are written back with the type of their column.
+#[derive(Debug, Default)]
+struct Selection {
+    root: Option<String>,
+    values: BTreeMap<Vec<String>, BTreeSet<Vec<String>>>,
+}
+
+impl Selection {
+    /// Add the values to those matched on these columns, telling whether any was new.
+    fn add(&mut self, columns: &[String], values: Vec<Vec<String>>) -> bool {
+        let known = self.values.entry(columns.to_vec()).or_default();
+        let before = known.len();
+        known.extend(values);
+
+        known.len() > before
+    }
+
+    fn is_empty(&self) -> bool {
+        self.root.is_none() && self.values.values().all(BTreeSet::is_empty)
+    }
+
+    /// Conditions on the rows of the table, given the types of its columns, together matching
+    /// the selected rows. Value lists are split so no condition grows past
+    /// `MAX_CONDITION_BYTES`, which keeps the queries under `max_allowed_packet`. Rows may
+    /// match several of them, unless the values are all on the same columns.
+    fn conditions(&self, types: Option<&HashMap<String, KeyType>>) -> Vec<String> {
+        let mut parts = Vec::new();
+
+        if let Some(root) = &self.root {
+            parts.push(format!("({})", root));
+        }
+
+        for (columns, values) in self.values.iter().filter(|(_, values)| !values.is_empty()) {
+            let tuple = |items: Vec<String>| {
+                if items.len() == 1 {
+                    items.into_iter().next().unwrap()
+                } else {
+                    format!("({})", items.join(", "))
+                }
+            };
+
+            let key_types = columns
+                .iter()
+                .map(|column| {
+                    types
+                        .and_then(|types| types.get(column))
+                        .unwrap_or(&KeyType::Binary)
+                })
+                .collect::<Vec<_>>();
+            let columns = tuple(columns.iter().map(|c| format!("`{}`", c)).collect());
+
+            let mut chunk: Vec<String> = Vec::new();
+            let mut chunk_bytes = 0;
+
+            for value in values {
+                let literal = tuple(
+                    value
+                        .iter()
+                        .zip(key_types.iter())
+                        .map(|(v, key_type)| key_type.literal(v))
+                        .collect(),
+                );
+
+                if !chunk.is_empty() && chunk_bytes + literal.len() > MAX_CONDITION_BYTES {
+                    parts.push(format!("{} IN ({})", columns, chunk.join(", ")));
+                    chunk.clear();
+                    chunk_bytes = 0;
+                }
+
+                chunk_bytes += literal.len() + 2;
+                chunk.push(literal);
+            }
+
+            parts.push(format!("{} IN ({})", columns, chunk.join(", ")));
+        }
+
+        parts
+    }
+}
+
+/// Compute, from the root filters, the conditions selecting the rows of each table so the
+/// subset is referentially intact: starting from the root rows, every row referencing a
+/// selected row is selected too (when following children), then every row referenced by a
+/// selected row, until nothing changes.
+///
+/// A table gets one or more conditions matching disjoint sets of rows, the selected rows
+/// being all of them, so large subsets are read in several bounded queries.
+///
+/// Only tables linked to a root through foreign keys get conditions, possibly matching no
+/// row at all. The others are not part of the subset and are copied as usual.
+pub(crate) async fn subset_conditions(
+    pool: &MySqlPool,
+    config: &SubsetConfig,
+) -> Result<HashMap<String, Vec<String>>, sqlx::Error> {
+    let foreign_keys = load_foreign_keys(pool).await?;
+    let types = load_column_types(pool).await?;
+
+    let mut selections: HashMap<String, Selection> = config
+        .roots
+        .iter()
+        .map(|(table, condition)| {
+            let selection = Selection {
+                root: Some(condition.clone()),
+                ..Selection::default()
+            };
+
+            (table.clone(), selection)
+        })
+        .collect();
+
+    // Children first: rows referencing selected rows, whatever their depth. Parents they
+    // pull in below do not bring in their own children, or the whole database would follow.
+    if config.follow_children {
+        let mut queue: VecDeque<String> = selections.keys().cloned().collect();
+
+        while let Some(parent) = queue.pop_front() {
+            for foreign_key in foreign_keys
+                .iter()
+                .filter(|foreign_key| foreign_key.referenced_table == parent)
+            {
+                let values = select_values(
+                    pool,
+                    &types,
+                    &parent,
+                    &foreign_key.referenced_columns,
+                    &selections[&parent],
+                )
+                .await?;
+
+                let grew = selections
+                    .entry(foreign_key.table.clone())
+                    .or_default()
+                    .add(&foreign_key.columns, values);
+
+                if grew && !queue.contains(&foreign_key.table) {
+                    queue.push_back(foreign_key.table.clone());
+                }
+            }
+        }
+    }
+
+    // Then parents: rows referenced by any selected row, so no foreign key dangles.
+    let mut queue: VecDeque<String> = selections.keys().cloned().collect();
+
+    while let Some(child) = queue.pop_front() {
+        for foreign_key in foreign_keys
+            .iter()
+            .filter(|foreign_key| foreign_key.table == child)
+        {
+            let values = select_values(
+                pool,
+                &types,
+                &child,
+                &foreign_key.columns,
+                &selections[&child],
+            )
+            .await?;
+
+            let grew = selections
+                .entry(foreign_key.referenced_table.clone())
+                .or_default()
+                .add(&foreign_key.referenced_columns, values);
+
+            if grew && !queue.contains(&foreign_key.referenced_table) {
+                queue.push_back(foreign_key.referenced_table.clone());
+            }
+        }
+    }
+
+    let primary_keys = load_primary_keys(pool).await?;
+    let mut conditions = HashMap::new();
+
+    for table in linked_tables(&foreign_keys, config) {
+        let Some(selection) = selections.get(&table) else {
+            conditions.insert(table, vec!["FALSE".to_string()]);
+
+            continue;
+        };
+
+        // Rows are read with one query per condition, which must not overlap or rows would be
+        // copied twice. Primary key values split over several conditions never do.
+        let parts = match primary_keys.get(&table) {
+            Some(primary_key) => {
+                let mut keys = Selection::default();
+                let values = select_values(pool, &types, &table, primary_key, selection).await?;
+                keys.add(primary_key, values);
+
+                keys.conditions(types.get(&table))
+            }
+            None => {
+                let parts = selection.conditions(types.get(&table));
+                if parts.len() > 1 {
+                    tracing::warn!(
+                        "{} has no primary key, its subset is read in a single query which may exceed max_allowed_packet",
+                        table
+                    );
+                }
+
+                if parts.is_empty() {
+                    parts
+                } else {
+                    vec![parts.join(" OR ")]
+                }
+            }
+        };
+
+        if parts.is_empty() {
+            conditions.insert(table, vec!["FALSE".to_string()]);
+        } else {
+            conditions.insert(table, parts);
+        }
+    }
+
+    Ok(conditions)
+}
+
+async fn load_foreign_keys(pool: &MySqlPool) -> Result<Vec<ForeignKey>, sqlx::Error> {
+    let rows = sqlx::query(SELECT_FOREIGN_KEYS).fetch_all(pool).await?;
+
+    let mut foreign_keys: Vec<ForeignKey> = Vec::new();
+    let mut current_constraint = None;
+
+    for row in rows {
+        let table = row.try_get::<String, usize>(0)?;
+        let constraint = row.try_get::<String, usize>(1)?;
+        let column = row.try_get::<String, usize>(2)?;
+        let referenced_table = row.try_get::<String, usize>(3)?;
+        let referenced_column = row.try_get::<String, usize>(4)?;
+
+        // Columns of a composite key come in a row each, one after the other.
+        let constraint = Some((table.clone(), constraint));
+        if constraint == current_constraint
+            && let Some(foreign_key) = foreign_keys.last_mut()
+        {
+            foreign_key.columns.push(column);
+            foreign_key.referenced_columns.push(referenced_column);
+
+            continue;
+        }
+
+        current_constraint = constraint;
+        foreign_keys.push(ForeignKey {
+            table,
+            columns: vec![column],
+            referenced_table,
+            referenced_columns: vec![referenced_column],
+        });
+    }
+
+    Ok(foreign_keys)
+}
+
+/// Columns of the primary key of each table having one, in order.
+async fn load_primary_keys(pool: &MySqlPool) -> Result<HashMap<String, Vec<String>>, sqlx::Error> {
+    let mut primary_keys: HashMap<String, Vec<String>> = HashMap::new();
+
+    for row in sqlx::query(SELECT_PRIMARY_KEYS).fetch_all(pool).await? {
+        primary_keys
+            .entry(row.try_get(0)?)
+            .or_default()
+            .push(row.try_get(1)?);
+    }
+
+    Ok(primary_keys)
+}
+
+async fn load_column_types(pool: &MySqlPool) -> Result<ColumnTypes, sqlx::Error> {
+    let mut types: ColumnTypes = HashMap::new();
+
+    for row in sqlx::query(SELECT_COLUMN_TYPES).fetch_all(pool).await? {
+        let key_type = KeyType::new(
+            &row.try_get::<String, usize>(2)?.to_lowercase(),
+            &row.try_get::<String, usize>(3)?.to_lowercase(),
+            row.try_get(4)?,
+            row.try_get(5)?,
+        );
+
+        types
+            .entry(row.try_get(0)?)
+            .or_default()
+            .insert(row.try_get(1)?, key_type);
+    }
+
+    Ok(types)
+}
+
+/// Distinct non NULL values of the columns over the selected rows of the table.
+async fn select_values(
+    pool: &MySqlPool,
+    types: &ColumnTypes,
+    table: &str,
+    columns: &[String],
+    selection: &Selection,
+) -> Result<Vec<Vec<String>>, sqlx::Error> {
+    if selection.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let hex_columns = columns
+        .iter()
+        .map(|column| format!("HEX(CAST(`{}` AS BINARY))", column))
+        .collect::<Vec<_>>();
+    let not_null = columns
+        .iter()
+        .map(|column| format!("`{}` IS NOT NULL", column))
+        .collect::<Vec<_>>();
+
+    // One query per condition, rows matching several of them are merged here.
+    let mut values = BTreeSet::new();
+
+    for condition in selection.conditions(types.get(table)) {
+        let query = format!(
+            "SELECT DISTINCT {} FROM `{}` WHERE ({}) AND {}",
+            hex_columns.join(", "),
+            table,
+            condition,
+            not_null.join(" AND ")
+        );
+
+        for row in sqlx::query(AssertSqlSafe(query)).fetch_all(pool).await? {
+            values.insert(
+                (0..columns.len())
+                    .map(|i| row.try_get::<String, usize>(i))
+                    .collect::<Result<Vec<_>, _>>()?,
+            );
+        }
+    }
+
+    Ok(values.into_iter().collect())
+}
+
+/// Tables reachable from a root by following foreign keys in either direction.
+fn linked_tables(foreign_keys: &[ForeignKey], config: &SubsetConfig) -> HashSet<String> {
+    let mut linked: HashSet<String> = config.roots.keys().cloned().collect();
+    let mut queue: VecDeque<String> = linked.iter().cloned().collect();
+
+    while let Some(table) = queue.pop_front() {
+        for foreign_key in foreign_keys.iter() {
+            let other = if foreign_key.table == table {
+                &foreign_key.referenced_table
+            } else if foreign_key.referenced_table == table {
+                &foreign_key.table
+            } else {
+                continue;
+            };
+
+            if linked.insert(other.clone()) {
+                queue.push_back(other.clone());
+            }
+        }
+    }
+
+    linked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn large_value_lists_are_split_without_losing_any() {
+        let columns = vec!["id".to_string()];
+        let values = (0..200_000)
+            .map(|i: u32| vec![format!("{:08X}", i)])
+            .collect::<Vec<_>>();
+        let mut selection = Selection::default();
+        selection.add(&columns, values);
+
+        let mut types = HashMap::new();
+        types.insert("id".to_string(), KeyType::Unsigned);
+        let conditions = selection.conditions(Some(&types));
+
+        assert!(conditions.len() > 1);
+        assert!(
+            conditions
+                .iter()
+                .all(|condition| condition.len() <= MAX_CONDITION_BYTES + 64)
+        );
+        assert_eq!(
+            conditions
+                .iter()
+                .map(|condition| condition.matches("CAST(").count())
+                .sum::<usize>(),
+            200_000
+        );
+        assert!(conditions[0].starts_with("`id` IN (CAST(UNHEX('00000000') AS UNSIGNED), "));
+    }
+
+    #[test]
+    fn root_condition_comes_first() {
+        let mut selection = Selection {
+            root: Some("`country` = 'FR'".to_string()),
+            ..Selection::default()
+        };
+        selection.add(&["id".to_string()], vec![vec!["2A".to_string()]]);
+
+        assert_eq!(
+            selection.conditions(None),
+            vec!["(`country` = 'FR')", "`id` IN (X'2A')"]
+        );
+        assert!(Selection::default().conditions(None).is_empty());
+    }
+}
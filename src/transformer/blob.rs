@@ -0,0 +1,82 @@
+use crate::transformer::{cannot_transform, text_like};
+use crate::value::MysqlValueDecoded;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Smallest valid files of each type recognized, by their leading bytes: a transparent
+/// 1x1 PNG and GIF, a grey 1x1 JPEG and a PDF of one empty page.
+const PLACEHOLDERS: &[(&[u8], &[u8])] = &[
+    (
+        b"\x89PNG\r\n\x1a\n",
+        include_bytes!("placeholders/placeholder.png"),
+    ),
+    (
+        b"\xff\xd8\xff",
+        include_bytes!("placeholders/placeholder.jpg"),
+    ),
+    (b"GIF87a", include_bytes!("placeholders/placeholder.gif")),
+    (b"GIF89a", include_bytes!("placeholders/placeholder.gif")),
+    (b"%PDF-", include_bytes!("placeholders/placeholder.pdf")),
+];
+
+/// Replace a file with a tiny one of the same type, so code reading the column still gets
+/// a valid image or document. Files of other types are emptied.
+pub(crate) fn placeholder(value: MysqlValueDecoded) -> MysqlValueDecoded {
+    match &value {
+        MysqlValueDecoded::Null => value,
+        MysqlValueDecoded::Bytes(bytes) => {
+            let placeholder = PLACEHOLDERS
+                .iter()
+                .find(|(magic, _)| bytes.starts_with(magic))
+                .map_or(&[][..], |(_, placeholder)| *placeholder);
+
+            MysqlValueDecoded::Bytes(placeholder.to_vec())
+        }
+        _ => cannot_transform(format_args!("cannot replace a value which is not binary")),
+    }
+}
+
+/// SHA-256 of the content, in hexadecimal: identical files still compare equal, their
+/// content is gone.
+pub(crate) fn content_hash(value: MysqlValueDecoded) -> MysqlValueDecoded {
+    let hash = match &value {
+        MysqlValueDecoded::Null => return value,
+        MysqlValueDecoded::String(text) => Sha256::digest(text.as_bytes()),
+        MysqlValueDecoded::Bytes(bytes) => Sha256::digest(bytes),
+        _ => {
+            return cannot_transform(format_args!(
+                "cannot hash a value which is not text or binary"
+            ));
+        }
+    };
+
+    let hex = hash.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+    text_like(value, hex)
+}
+
+/// Keep the first `length` bytes of binary values, the first `length` characters of text.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct Truncate {
+    length: usize,
+}
+
+impl Truncate {
+    pub(crate) fn transform(&self, value: MysqlValueDecoded) -> MysqlValueDecoded {
+        match value {
+            MysqlValueDecoded::Null => value,
+            MysqlValueDecoded::Bytes(mut bytes) => {
+                bytes.truncate(self.length);
+
+                MysqlValueDecoded::Bytes(bytes)
+            }
+            MysqlValueDecoded::String(text) => match text.char_indices().nth(self.length) {
+                Some((end, _)) => MysqlValueDecoded::String(text[..end].to_string()),
+                None => MysqlValueDecoded::String(text),
+            },
+            _ => cannot_transform(format_args!(
+                "cannot truncate a value which is not text or binary"
+            )),
+        }
+    }
+}
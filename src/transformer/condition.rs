@@ -0,0 +1,160 @@
+use crate::transformer::{Pattern, RowContext};
+use crate::value::MysqlValueDecoded;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::cmp::Ordering;
+
+/// Predicate over the source values of a row, deciding whether a transformer applies to it.
+///
+/// Every test given must hold. Tests read `column`, the transformed column itself when not
+/// set, and nested conditions inherit it. As in SQL, comparing NULL is never true, only
+/// `is_null` matches it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Condition {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    column: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    eq: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ne: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lt: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    le: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gt: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ge: Option<Value>,
+    #[serde(rename = "in", skip_serializing_if = "Option::is_none")]
+    in_list: Option<Vec<Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    not_in: Option<Vec<Value>>,
+    /// Regular expression searched in the text of the value.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matches: Option<Pattern>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    is_null: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    all: Option<Vec<Condition>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    any: Option<Vec<Condition>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    not: Option<Box<Condition>>,
+}
+
+impl Condition {
+    pub(crate) fn evaluate(&self, column: &str, row: &RowContext) -> bool {
+        let column = self.column.as_deref().unwrap_or(column);
+
+        if let Some(all) = &self.all
+            && !all.iter().all(|condition| condition.evaluate(column, row))
+        {
+            return false;
+        }
+
+        if let Some(any) = &self.any
+            && !any.iter().any(|condition| condition.evaluate(column, row))
+        {
+            return false;
+        }
+
+        if let Some(not) = &self.not
+            && not.evaluate(column, row)
+        {
+            return false;
+        }
+
+        if !self.has_value_tests() {
+            return true;
+        }
+
+        let value = match row.original(column) {
+            Some(value) => value,
+            None => {
+                tracing::warn!("unknown column {} in condition", column);
+
+                &MysqlValueDecoded::Null
+            }
+        };
+
+        let ordering = |expected: &Option<Value>, accept: fn(Ordering) -> bool| {
+            expected
+                .as_ref()
+                .is_none_or(|expected| compare(value, expected).is_some_and(accept))
+        };
+
+        ordering(&self.eq, Ordering::is_eq)
+            && ordering(&self.ne, Ordering::is_ne)
+            && ordering(&self.lt, Ordering::is_lt)
+            && ordering(&self.le, Ordering::is_le)
+            && ordering(&self.gt, Ordering::is_gt)
+            && ordering(&self.ge, Ordering::is_ge)
+            && self.in_list.as_ref().is_none_or(|list| {
+                list.iter()
+                    .any(|expected| compare(value, expected) == Some(Ordering::Equal))
+            })
+            && self.not_in.as_ref().is_none_or(|list| {
+                !matches!(value, MysqlValueDecoded::Null)
+                    && list
+                        .iter()
+                        .all(|expected| compare(value, expected) != Some(Ordering::Equal))
+            })
+            && self
+                .matches
+                .as_ref()
+                .is_none_or(|pattern| value.as_text().is_some_and(|text| pattern.is_match(&text)))
+            && self
+                .is_null
+                .is_none_or(|is_null| matches!(value, MysqlValueDecoded::Null) == is_null)
+    }
+
+    fn has_value_tests(&self) -> bool {
+        self.eq.is_some()
+            || self.ne.is_some()
+            || self.lt.is_some()
+            || self.le.is_some()
+            || self.gt.is_some()
+            || self.ge.is_some()
+            || self.in_list.is_some()
+            || self.not_in.is_some()
+            || self.matches.is_some()
+            || self.is_null.is_some()
+    }
+}
+
+/// Order of the value against one from the configuration: as numbers when the expected value
+/// is a number or a boolean, as text when it is a string. None when either one is NULL or
+/// they cannot be compared.
+fn compare(value: &MysqlValueDecoded, expected: &Value) -> Option<Ordering> {
+    match expected {
+        Value::Number(number) => {
+            let integer = number
+                .as_i64()
+                .map(i128::from)
+                .or_else(|| number.as_u64().map(i128::from));
+
+            match (as_integer(value), integer) {
+                (Some(value), Some(expected)) => Some(value.cmp(&expected)),
+                _ => as_float(value)?.partial_cmp(&number.as_f64()?),
+            }
+        }
+        Value::Bool(expected) => Some(as_integer(value)?.cmp(&i128::from(*expected))),
+        Value::String(expected) => Some(value.as_text()?.as_ref().cmp(expected.as_str())),
+        _ => None,
+    }
+}
+
+fn as_integer(value: &MysqlValueDecoded) -> Option<i128> {
+    match value {
+        MysqlValueDecoded::Bool(b) => Some(i128::from(*b)),
+        value => value.as_i128(),
+    }
+}
+
+fn as_float(value: &MysqlValueDecoded) -> Option<f64> {
+    match value {
+        MysqlValueDecoded::Double(f) => Some(*f),
+        value => value.as_text()?.trim().parse().ok(),
+    }
+}
@@ -0,0 +1,196 @@
+use crate::transformer::{RowContext, cannot_transform, resolve_secret, text_like};
+use crate::value::MysqlValueDecoded;
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Timelike};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fmt;
+use std::sync::LazyLock;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Key of the shifts seeded by a column when no secret is configured: random, so the shifts
+/// cannot be recomputed, and shared so an entity is shifted the same way in every table of
+/// the run.
+static RUN_KEY: LazyLock<[u8; 32]> = LazyLock::new(rand::random);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct DateShiftConfig {
+    /// Bounds of the offset, in days, `-max_days` when not set.
+    #[serde(default)]
+    min_days: Option<i64>,
+    max_days: i64,
+    /// Column whose source value picks the offset, so all the rows of an entity move
+    /// together and the intervals between them are kept.
+    #[serde(default)]
+    seed_column: Option<String>,
+    #[serde(default)]
+    secret: Option<String>,
+    #[serde(default)]
+    secret_env: Option<String>,
+}
+
+/// Move dates and times by a random number of days within the configured range, keeping
+/// the time of day. DATETIME and TIMESTAMP values are shifted, as are dates and date times
+/// stored as text. Zero dates are kept, anything else is set to NULL.
+///
+/// With `seed_column` the offset derives from an HMAC of that column: keyed with the secret
+/// the same entity gets the same offset in every run, without it only during the run.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(try_from = "DateShiftConfig", into = "DateShiftConfig")]
+pub(crate) struct DateShift {
+    config: DateShiftConfig,
+    min_days: i64,
+    key: Vec<u8>,
+}
+
+impl TryFrom<DateShiftConfig> for DateShift {
+    type Error = String;
+
+    fn try_from(config: DateShiftConfig) -> Result<Self, Self::Error> {
+        let min_days = config.min_days.unwrap_or(-config.max_days);
+        if min_days > config.max_days {
+            return Err(format!(
+                "min_days {} is above max_days {}",
+                min_days, config.max_days
+            ));
+        }
+
+        let key = match (&config.secret, &config.secret_env) {
+            (None, None) => RUN_KEY.to_vec(),
+            (secret, secret_env) => resolve_secret(secret.as_deref(), secret_env.as_deref())?,
+        };
+
+        Ok(Self {
+            config,
+            min_days,
+            key,
+        })
+    }
+}
+
+impl From<DateShift> for DateShiftConfig {
+    fn from(date_shift: DateShift) -> Self {
+        date_shift.config
+    }
+}
+
+// The secret must not end up in the logs.
+impl fmt::Debug for DateShift {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DateShift")
+            .field("min_days", &self.min_days)
+            .field("max_days", &self.config.max_days)
+            .field("seed_column", &self.config.seed_column)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DateShift {
+    pub(crate) fn transform(
+        &self,
+        value: MysqlValueDecoded,
+        row: &RowContext,
+    ) -> MysqlValueDecoded {
+        let seed = match &self.config.seed_column {
+            Some(column) => match row.original(column) {
+                Some(seed) => seed.as_bytes().map(|seed| {
+                    let mut mac =
+                        HmacSha256::new_from_slice(&self.key).expect("HMAC accepts any key size");
+                    mac.update(&seed);
+                    let digest = mac.finalize().into_bytes();
+
+                    u64::from_be_bytes(digest[..8].try_into().unwrap())
+                }),
+                None => {
+                    tracing::warn!("unknown seed column {} for date_shift", column);
+
+                    None
+                }
+            },
+            None => None,
+        };
+
+        // Rows without an entity get an offset of their own.
+        let seed = seed.unwrap_or_else(rand::random);
+        let span = (self.config.max_days - self.min_days) as u64 + 1;
+        let days = self.min_days + (seed % span) as i64;
+
+        map_datetime(value, |datetime| {
+            datetime.checked_add_signed(TimeDelta::try_days(days)?)
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum DateUnit {
+    Year,
+    Month,
+    Day,
+    Hour,
+}
+
+/// Lower the precision of dates and times to the unit, as the first instant of it: with
+/// `month`, `2020-05-17 10:42:00` becomes `2020-05-01 00:00:00`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct DateTruncate {
+    unit: DateUnit,
+}
+
+impl DateTruncate {
+    pub(crate) fn transform(&self, value: MysqlValueDecoded) -> MysqlValueDecoded {
+        map_datetime(value, |datetime| {
+            let date = datetime.date();
+            let (date, time) = match self.unit {
+                DateUnit::Year => (date.with_ordinal(1)?, NaiveTime::MIN),
+                DateUnit::Month => (date.with_day(1)?, NaiveTime::MIN),
+                DateUnit::Day => (date, NaiveTime::MIN),
+                DateUnit::Hour => (date, NaiveTime::from_hms_opt(datetime.hour(), 0, 0)?),
+            };
+
+            Some(date.and_time(time))
+        })
+    }
+}
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+
+/// Apply `f` to the date and time of a DATETIME or TIMESTAMP value, or of a date or date
+/// time written as text, keeping the form of the value. Zero dates, which tell nothing, are
+/// kept. Other values which are not dates, or for which `f` gives nothing, are set to NULL.
+fn map_datetime(
+    value: MysqlValueDecoded,
+    f: impl Fn(NaiveDateTime) -> Option<NaiveDateTime>,
+) -> MysqlValueDecoded {
+    match &value {
+        MysqlValueDecoded::DateTime(datetime) => match f(datetime.naive_utc()) {
+            Some(datetime) => MysqlValueDecoded::DateTime(datetime.and_utc()),
+            None => cannot_transform(format_args!("date out of range")),
+        },
+        MysqlValueDecoded::String(_) | MysqlValueDecoded::Bytes(_) => {
+            let text = value.as_text().unwrap_or_default();
+
+            if text.starts_with("0000-00-00") {
+                return value;
+            }
+
+            let mapped = if let Ok(date) = NaiveDate::parse_from_str(&text, DATE_FORMAT) {
+                f(date.and_time(NaiveTime::MIN))
+                    .map(|datetime| datetime.format(DATE_FORMAT).to_string())
+            } else if let Ok(datetime) = NaiveDateTime::parse_from_str(&text, DATETIME_FORMAT) {
+                f(datetime).map(|datetime| datetime.format(DATETIME_FORMAT).to_string())
+            } else {
+                None
+            };
+
+            match mapped {
+                Some(text) => text_like(value, text),
+                None => cannot_transform(format_args!("not a date or out of range")),
+            }
+        }
+        MysqlValueDecoded::Null => value,
+        _ => cannot_transform(format_args!("not a date")),
+    }
+}
@@ -0,0 +1,203 @@
+use crate::transformer::text_like;
+use crate::value::MysqlValueDecoded;
+use fake::Fake as _;
+use fake::faker::impls::address::CityNameGenFn;
+use fake::faker::{address, company, internet, job, lorem, name, phone_number};
+use fake::locales::{self, Data};
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use rand_chacha::rand_core::SeedableRng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum FakeKind {
+    Name,
+    FirstName,
+    LastName,
+    Email,
+    Username,
+    PhoneNumber,
+    CellNumber,
+    /// Building number and street name.
+    StreetAddress,
+    City,
+    ZipCode,
+    Country,
+    /// Street address, zip code and city.
+    Address,
+    Company,
+    JobTitle,
+    /// An IBAN of the locale's country, with valid check digits.
+    Iban,
+    /// A random (version 4) UUID.
+    Uuid,
+    Word,
+    Sentence,
+    Paragraph,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum FakeLocale {
+    #[default]
+    En,
+    FrFr,
+    DeDe,
+    ItIt,
+    PtBr,
+    PtPt,
+    JaJp,
+    ZhCn,
+    ZhTw,
+    ArSa,
+    CyGb,
+}
+
+/// Replace values by realistic fake data. By default each row gets a new random value,
+/// with `seeded` the generator is seeded from the original value instead, so the same
+/// input always gives the same fake value.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct Fake {
+    kind: FakeKind,
+    #[serde(default)]
+    locale: FakeLocale,
+    #[serde(default)]
+    seeded: bool,
+}
+
+impl Fake {
+    pub(crate) fn transform(&self, value: MysqlValueDecoded) -> MysqlValueDecoded {
+        // There is nothing to hide in a NULL, and a fake value would invent data.
+        let Some(input) = value.as_bytes() else {
+            return value;
+        };
+
+        let text = if self.seeded {
+            let seed: [u8; 32] = Sha256::digest(&input).into();
+
+            self.generate(&mut ChaCha8Rng::from_seed(seed))
+        } else {
+            self.generate(&mut rand::rng())
+        };
+
+        text_like(value, text)
+    }
+
+    fn generate<R: Rng>(&self, rng: &mut R) -> String {
+        match self.locale {
+            FakeLocale::En => generate(self.kind, locales::EN, "GB", rng),
+            FakeLocale::FrFr => generate(self.kind, locales::FR_FR, "FR", rng),
+            FakeLocale::DeDe => generate(self.kind, locales::DE_DE, "DE", rng),
+            FakeLocale::ItIt => generate(self.kind, locales::IT_IT, "IT", rng),
+            FakeLocale::PtBr => generate(self.kind, locales::PT_BR, "BR", rng),
+            FakeLocale::PtPt => generate(self.kind, locales::PT_PT, "PT", rng),
+            FakeLocale::JaJp => generate(self.kind, locales::JA_JP, "GB", rng),
+            FakeLocale::ZhCn => generate(self.kind, locales::ZH_CN, "GB", rng),
+            FakeLocale::ZhTw => generate(self.kind, locales::ZH_TW, "GB", rng),
+            FakeLocale::ArSa => generate(self.kind, locales::AR_SA, "SA", rng),
+            FakeLocale::CyGb => generate(self.kind, locales::CY_GB, "GB", rng),
+        }
+    }
+}
+
+/// `country` is the one IBANs are generated for, countries without IBAN falling back to GB.
+fn generate<L: CityNameGenFn, R: Rng>(
+    kind: FakeKind,
+    locale: L,
+    country: &str,
+    rng: &mut R,
+) -> String {
+    match kind {
+        FakeKind::Name => name::raw::Name(locale).fake_with_rng(rng),
+        FakeKind::FirstName => name::raw::FirstName(locale).fake_with_rng(rng),
+        FakeKind::LastName => name::raw::LastName(locale).fake_with_rng(rng),
+        FakeKind::Email => internet::raw::SafeEmail(locale).fake_with_rng(rng),
+        FakeKind::Username => internet::raw::Username(locale).fake_with_rng(rng),
+        FakeKind::PhoneNumber => phone_number::raw::PhoneNumber(locale).fake_with_rng(rng),
+        FakeKind::CellNumber => phone_number::raw::CellNumber(locale).fake_with_rng(rng),
+        FakeKind::StreetAddress => street_address(locale, rng),
+        FakeKind::City => address::raw::CityName(locale).fake_with_rng(rng),
+        FakeKind::ZipCode => address::raw::ZipCode(locale).fake_with_rng(rng),
+        FakeKind::Country => address::raw::CountryName(locale).fake_with_rng(rng),
+        FakeKind::Address => {
+            let street = street_address(locale, rng);
+            let zip_code: String = address::raw::ZipCode(locale).fake_with_rng(rng);
+            let city: String = address::raw::CityName(locale).fake_with_rng(rng);
+
+            format!("{}, {} {}", street, zip_code, city)
+        }
+        FakeKind::Company => company::raw::CompanyName(locale).fake_with_rng(rng),
+        FakeKind::JobTitle => job::raw::Title(locale).fake_with_rng(rng),
+        FakeKind::Iban => iban(country, rng),
+        FakeKind::Uuid => uuid(rng),
+        FakeKind::Word => lorem::raw::Word(locale).fake_with_rng(rng),
+        FakeKind::Sentence => lorem::raw::Sentence(locale, 4..10).fake_with_rng(rng),
+        FakeKind::Paragraph => lorem::raw::Paragraph(locale, 3..7).fake_with_rng(rng),
+    }
+}
+
+fn street_address<L: Data + Copy, R: Rng>(locale: L, rng: &mut R) -> String {
+    let number: String = address::raw::BuildingNumber(locale).fake_with_rng(rng);
+    let street: String = address::raw::StreetName(locale).fake_with_rng(rng);
+
+    format!("{} {}", number, street)
+}
+
+/// Layout of the national part of the IBAN of each country: `9` for a digit, `A` for an
+/// uppercase letter.
+fn bban_layout(country: &str) -> &'static str {
+    match country {
+        "FR" => "99999999999999999999999",
+        "DE" => "999999999999999999",
+        "IT" => "A9999999999999999999999",
+        "BR" => "99999999999999999999999A9",
+        "PT" => "999999999999999999999",
+        "SA" => "99999999999999999999",
+        // GB
+        _ => "AAAA99999999999999",
+    }
+}
+
+fn iban<R: Rng>(country: &str, rng: &mut R) -> String {
+    let bban: String = bban_layout(country)
+        .chars()
+        .map(|c| match c {
+            'A' => char::from(rng.random_range(b'A'..=b'Z')),
+            _ => char::from(rng.random_range(b'0'..=b'9')),
+        })
+        .collect();
+
+    // ISO 13616: the check digits make the number, with the country code and the check
+    // digits moved at the end and letters counted from 10, equal to 1 modulo 97.
+    let remainder = format!("{}{}00", bban, country)
+        .chars()
+        .map(|c| c.to_digit(36).unwrap())
+        .fold(0, |remainder, digit| {
+            if digit >= 10 {
+                (remainder * 100 + digit) % 97
+            } else {
+                (remainder * 10 + digit) % 97
+            }
+        });
+
+    format!("{}{:02}{}", country, 98 - remainder, bban)
+}
+
+fn uuid<R: Rng>(rng: &mut R) -> String {
+    let mut bytes: [u8; 16] = rng.random();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
@@ -0,0 +1,294 @@
+use crate::transformer::{cannot_transform, resolve_secret, text_like};
+use crate::value::MysqlValueDecoded;
+use aes::Aes128;
+use aes::cipher::{BlockEncrypt, KeyInit};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum IpMode {
+    /// Keep the network prefix, zero the host bits.
+    #[default]
+    Truncate,
+    /// Crypto-PAn: a keyed permutation where addresses sharing a prefix of any length still
+    /// share a prefix of that length once anonymized.
+    CryptoPan,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct IpAnonymizeConfig {
+    #[serde(default)]
+    mode: IpMode,
+    /// Bits kept by `truncate`.
+    #[serde(default = "default_ipv4_prefix")]
+    ipv4_prefix: u32,
+    #[serde(default = "default_ipv6_prefix")]
+    ipv6_prefix: u32,
+    #[serde(default)]
+    secret: Option<String>,
+    #[serde(default)]
+    secret_env: Option<String>,
+}
+
+fn default_ipv4_prefix() -> u32 {
+    24
+}
+
+fn default_ipv6_prefix() -> u32 {
+    48
+}
+
+/// Anonymize IPv4 and IPv6 addresses, written as text, as 4 or 16 bytes (`INET6_ATON`) or
+/// as an integer (`INET_ATON`), keeping their representation. Other values are set to NULL.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(try_from = "IpAnonymizeConfig", into = "IpAnonymizeConfig")]
+pub(crate) struct IpAnonymize {
+    config: IpAnonymizeConfig,
+    crypto_pan: Option<Arc<CryptoPan>>,
+}
+
+impl TryFrom<IpAnonymizeConfig> for IpAnonymize {
+    type Error = String;
+
+    fn try_from(config: IpAnonymizeConfig) -> Result<Self, Self::Error> {
+        if config.ipv4_prefix > 32 {
+            return Err(format!("ipv4_prefix {} is above 32", config.ipv4_prefix));
+        }
+
+        if config.ipv6_prefix > 128 {
+            return Err(format!("ipv6_prefix {} is above 128", config.ipv6_prefix));
+        }
+
+        let crypto_pan = match config.mode {
+            IpMode::Truncate => None,
+            IpMode::CryptoPan => {
+                let secret =
+                    resolve_secret(config.secret.as_deref(), config.secret_env.as_deref())?;
+
+                Some(Arc::new(CryptoPan::new(&Sha256::digest(secret))))
+            }
+        };
+
+        Ok(Self { config, crypto_pan })
+    }
+}
+
+impl From<IpAnonymize> for IpAnonymizeConfig {
+    fn from(ip_anonymize: IpAnonymize) -> Self {
+        ip_anonymize.config
+    }
+}
+
+// The secret must not end up in the logs.
+impl fmt::Debug for IpAnonymize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("IpAnonymize")
+            .field("mode", &self.config.mode)
+            .field("ipv4_prefix", &self.config.ipv4_prefix)
+            .field("ipv6_prefix", &self.config.ipv6_prefix)
+            .finish_non_exhaustive()
+    }
+}
+
+impl IpAnonymize {
+    pub(crate) fn transform(&self, value: MysqlValueDecoded) -> MysqlValueDecoded {
+        match &value {
+            // No IPv4 address is 4 characters long as text, and few IPv6 ones are: 4 bytes
+            // are always a raw IPv4 address, even when they read as text like `1::1`.
+            MysqlValueDecoded::Bytes(bytes) if bytes.len() == 4 => {
+                let address = Ipv4Addr::from(<[u8; 4]>::try_from(bytes.as_slice()).unwrap());
+
+                MysqlValueDecoded::Bytes(self.anonymize_v4(address).octets().to_vec())
+            }
+            // An address written in a binary column may be 16 characters long, 16 bytes are
+            // only read as raw ones when they are not an address as text.
+            MysqlValueDecoded::String(_) | MysqlValueDecoded::Bytes(_)
+                if let Some(address) =
+                    text(&value).and_then(|text| text.trim().parse::<IpAddr>().ok()) =>
+            {
+                let anonymized = match address {
+                    IpAddr::V4(address) => IpAddr::V4(self.anonymize_v4(address)),
+                    IpAddr::V6(address) => IpAddr::V6(self.anonymize_v6(address)),
+                };
+
+                text_like(value, anonymized.to_string())
+            }
+            MysqlValueDecoded::Bytes(bytes) if bytes.len() == 16 => {
+                let address = Ipv6Addr::from(<[u8; 16]>::try_from(bytes.as_slice()).unwrap());
+
+                MysqlValueDecoded::Bytes(self.anonymize_v6(address).octets().to_vec())
+            }
+            MysqlValueDecoded::Int(_) | MysqlValueDecoded::UInt(_)
+                if let Some(address) = value.as_i128().and_then(|i| u32::try_from(i).ok()) =>
+            {
+                let anonymized = u32::from(self.anonymize_v4(Ipv4Addr::from(address)));
+
+                match value {
+                    MysqlValueDecoded::Int(_) => MysqlValueDecoded::Int(anonymized.into()),
+                    _ => MysqlValueDecoded::UInt(anonymized.into()),
+                }
+            }
+            MysqlValueDecoded::Null => value,
+            _ => cannot_transform(format_args!("not an ip address")),
+        }
+    }
+
+    fn anonymize_v4(&self, address: Ipv4Addr) -> Ipv4Addr {
+        let bits = u128::from(u32::from(address)) << 96;
+
+        let anonymized = match &self.crypto_pan {
+            Some(crypto_pan) => crypto_pan.anonymize(bits, 32),
+            None => bits & prefix_mask(self.config.ipv4_prefix),
+        };
+
+        Ipv4Addr::from((anonymized >> 96) as u32)
+    }
+
+    fn anonymize_v6(&self, address: Ipv6Addr) -> Ipv6Addr {
+        let bits = u128::from(address);
+
+        Ipv6Addr::from(match &self.crypto_pan {
+            Some(crypto_pan) => crypto_pan.anonymize(bits, 128),
+            None => bits & prefix_mask(self.config.ipv6_prefix),
+        })
+    }
+}
+
+/// The value as text, binary values only when they are valid UTF-8.
+fn text(value: &MysqlValueDecoded) -> Option<&str> {
+    match value {
+        MysqlValueDecoded::String(text) => Some(text),
+        MysqlValueDecoded::Bytes(bytes) => std::str::from_utf8(bytes).ok(),
+        _ => None,
+    }
+}
+
+/// The first `prefix` bits set.
+fn prefix_mask(prefix: u32) -> u128 {
+    u128::MAX.checked_shl(128 - prefix).unwrap_or(0)
+}
+
+/// Prefix-preserving anonymization of Xu, Fan, Ammar and Moon, with AES-128: the first half
+/// of the 32 bytes key is the AES key, the second half, encrypted, pads the blocks.
+struct CryptoPan {
+    cipher: Aes128,
+    pad: u128,
+}
+
+impl CryptoPan {
+    fn new(key: &[u8]) -> Self {
+        let cipher = Aes128::new_from_slice(&key[..16]).expect("AES-128 takes a 16 bytes key");
+        let mut pad = <[u8; 16]>::try_from(&key[16..32]).unwrap().into();
+        cipher.encrypt_block(&mut pad);
+
+        Self {
+            cipher,
+            pad: u128::from_be_bytes(pad.into()),
+        }
+    }
+
+    /// Anonymize the first `bits` bits of the address, each one flipped or not depending on
+    /// the bits before it only.
+    fn anonymize(&self, address: u128, bits: u32) -> u128 {
+        let mut flips = 0u128;
+
+        for position in 0..bits {
+            let kept = prefix_mask(position);
+            let mut block = ((address & kept) | (self.pad & !kept)).to_be_bytes().into();
+            self.cipher.encrypt_block(&mut block);
+
+            flips |= u128::from(block[0] >> 7) << (127 - position);
+        }
+
+        address ^ flips
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Key of the sample run of the reference Crypto-PAn implementation.
+    const KEY: [u8; 32] = [
+        21, 34, 23, 141, 51, 164, 207, 128, 19, 10, 91, 22, 73, 144, 125, 16, 216, 152, 143, 131,
+        121, 121, 101, 39, 98, 87, 76, 45, 42, 132, 34, 2,
+    ];
+
+    fn anonymize(crypto_pan: &CryptoPan, address: &str) -> Ipv4Addr {
+        let bits = u128::from(u32::from(address.parse::<Ipv4Addr>().unwrap())) << 96;
+
+        Ipv4Addr::from((crypto_pan.anonymize(bits, 32) >> 96) as u32)
+    }
+
+    #[test]
+    fn crypto_pan_matches_the_reference_implementation() {
+        let crypto_pan = CryptoPan::new(&KEY);
+
+        for (address, anonymized) in [
+            ("128.11.68.132", "135.242.180.132"),
+            ("129.118.74.4", "134.136.186.123"),
+            ("130.132.252.244", "133.68.164.234"),
+            ("141.223.7.43", "141.167.8.160"),
+            ("141.233.145.108", "141.129.237.235"),
+        ] {
+            assert_eq!(anonymize(&crypto_pan, address).to_string(), anonymized);
+        }
+    }
+
+    #[test]
+    fn crypto_pan_preserves_shared_prefixes() {
+        let crypto_pan = CryptoPan::new(&KEY);
+        let addresses = [
+            "10.1.2.3",
+            "10.1.2.200",
+            "10.1.130.3",
+            "10.200.2.3",
+            "192.168.0.1",
+        ];
+
+        for a in addresses {
+            for b in addresses {
+                let shared =
+                    |a: Ipv4Addr, b: Ipv4Addr| (u32::from(a) ^ u32::from(b)).leading_zeros();
+
+                assert_eq!(
+                    shared(anonymize(&crypto_pan, a), anonymize(&crypto_pan, b)),
+                    shared(a.parse().unwrap(), b.parse().unwrap())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn text_in_a_binary_column_is_anonymized_as_text() {
+        let ip: IpAnonymize = serde_yaml::from_str("ipv4_prefix: 16").unwrap();
+
+        match ip.transform(MysqlValueDecoded::Bytes(b"192.168.12.34".to_vec())) {
+            MysqlValueDecoded::Bytes(bytes) => assert_eq!(bytes, b"192.168.0.0"),
+            value => panic!("unexpected value {:?}", value),
+        }
+        match ip.transform(MysqlValueDecoded::Bytes(vec![192, 168, 12, 34])) {
+            MysqlValueDecoded::Bytes(bytes) => assert_eq!(bytes, [192, 168, 0, 0]),
+            value => panic!("unexpected value {:?}", value),
+        }
+        match ip.transform(MysqlValueDecoded::Bytes(b"2001:db8::8a2e:1".to_vec())) {
+            MysqlValueDecoded::Bytes(bytes) => assert_eq!(bytes, b"2001:db8::"),
+            value => panic!("unexpected value {:?}", value),
+        }
+    }
+
+    #[test]
+    fn four_bytes_are_a_raw_ipv4_address_even_when_they_read_as_text() {
+        let ip: IpAnonymize = serde_yaml::from_str("ipv4_prefix: 16").unwrap();
+
+        // 49.58.58.49, which reads as the IPv6 address 1::1.
+        match ip.transform(MysqlValueDecoded::Bytes(b"1::1".to_vec())) {
+            MysqlValueDecoded::Bytes(bytes) => assert_eq!(bytes, [49, 58, 0, 0]),
+            value => panic!("unexpected value {:?}", value),
+        }
+    }
+}
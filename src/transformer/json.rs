@@ -0,0 +1,141 @@
+use crate::transformer::{RowContext, TransformerPipeline, cannot_transform, path_pipelines};
+use crate::value::MysqlValueDecoded;
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+use serde_json_path::JsonPath;
+use serde_yaml::Mapping;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct JsonConfig {
+    /// JSONPath expressions, each with the transformers of the nodes it selects.
+    paths: Mapping,
+}
+
+/// Apply transformers to the nodes of a JSON document selected by JSONPath expressions
+/// (RFC 9535), like `$.contacts[*].email`. Paths are applied in the order of the
+/// configuration, each to the document as left by the previous ones.
+///
+/// Each node is given to the transformers as a value of its type: strings, numbers,
+/// booleans and null as such, arrays and objects as their JSON text. What they return is
+/// stored back as the closest JSON value. A document which cannot be decoded is set to
+/// NULL.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "JsonConfig", into = "JsonConfig")]
+pub(crate) struct Json {
+    config: JsonConfig,
+    paths: Vec<(JsonPath, TransformerPipeline)>,
+}
+
+impl TryFrom<JsonConfig> for Json {
+    type Error = String;
+
+    fn try_from(config: JsonConfig) -> Result<Self, Self::Error> {
+        let paths = path_pipelines(&config.paths, |path| {
+            JsonPath::parse(path).map_err(|e| format!("invalid json path {:?}: {}", path, e))
+        })?;
+
+        Ok(Self { config, paths })
+    }
+}
+
+impl From<Json> for JsonConfig {
+    fn from(json: Json) -> Self {
+        json.config
+    }
+}
+
+impl Json {
+    /// Whether the transformers of the nodes read the transformed value of the column.
+    pub(crate) fn reads(&self, column: &str) -> bool {
+        self.paths
+            .iter()
+            .any(|(_, pipeline)| pipeline.reads(column))
+    }
+
+    pub(crate) fn transform(
+        &self,
+        value: MysqlValueDecoded,
+        row: &RowContext,
+    ) -> MysqlValueDecoded {
+        let document = match &value {
+            MysqlValueDecoded::String(json) => serde_json::from_str::<Value>(json),
+            MysqlValueDecoded::Bytes(json) => serde_json::from_slice::<Value>(json),
+            MysqlValueDecoded::Null => return value,
+            _ => return cannot_transform(format_args!("json only applies to text")),
+        };
+
+        let mut document = match document {
+            Ok(document) => document,
+            Err(e) => return cannot_transform(format_args!("failed to decode json {}", e)),
+        };
+
+        for (path, pipeline) in self.paths.iter() {
+            let pointers = path
+                .query_located(&document)
+                .locations()
+                .map(|location| location.to_json_pointer())
+                .collect::<Vec<_>>();
+
+            for pointer in pointers {
+                // A previous node may have replaced the one holding this node.
+                let Some(node) = document.pointer_mut(&pointer) else {
+                    continue;
+                };
+
+                let transformed = pipeline.apply(from_json(node), row);
+                *node = to_json(transformed, node);
+            }
+        }
+
+        match value {
+            MysqlValueDecoded::Bytes(_) => match serde_json::to_vec(&document) {
+                Ok(json) => MysqlValueDecoded::Bytes(json),
+                Err(e) => cannot_transform(format_args!("failed to encode json {}", e)),
+            },
+            _ => match serde_json::to_string(&document) {
+                Ok(json) => MysqlValueDecoded::String(json),
+                Err(e) => cannot_transform(format_args!("failed to encode json {}", e)),
+            },
+        }
+    }
+}
+
+fn from_json(node: &Value) -> MysqlValueDecoded {
+    match node {
+        Value::Null => MysqlValueDecoded::Null,
+        Value::Bool(b) => MysqlValueDecoded::Bool(*b),
+        Value::Number(number) => {
+            if let Some(i) = number.as_i64() {
+                MysqlValueDecoded::Int(i)
+            } else if let Some(u) = number.as_u64() {
+                MysqlValueDecoded::UInt(u)
+            } else {
+                MysqlValueDecoded::Double(number.as_f64().unwrap_or(f64::NAN))
+            }
+        }
+        Value::String(s) => MysqlValueDecoded::String(s.clone()),
+        Value::Array(_) | Value::Object(_) => MysqlValueDecoded::String(node.to_string()),
+    }
+}
+
+/// JSON value of a transformed node: text replacing an array or an object is parsed back
+/// when it is JSON, and decimals stay numbers.
+fn to_json(value: MysqlValueDecoded, node: &Value) -> Value {
+    match value {
+        MysqlValueDecoded::Null => Value::Null,
+        MysqlValueDecoded::Bool(b) => Value::Bool(b),
+        MysqlValueDecoded::Int(i) => Value::from(i),
+        MysqlValueDecoded::UInt(u) => Value::from(u),
+        MysqlValueDecoded::Double(f) => Number::from_f64(f).map_or(Value::Null, Value::Number),
+        MysqlValueDecoded::Decimal(d) => match d.to_string().parse::<Number>() {
+            Ok(number) => Value::Number(number),
+            Err(_) => Value::String(d.to_string()),
+        },
+        MysqlValueDecoded::String(s) if node.is_array() || node.is_object() => {
+            serde_json::from_str(&s).unwrap_or(Value::String(s))
+        }
+        value => value
+            .as_text()
+            .map_or(Value::Null, |text| Value::String(text.into_owned())),
+    }
+}
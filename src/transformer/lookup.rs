@@ -0,0 +1,314 @@
+use crate::transformer::{Replacement, text_like};
+use crate::value::MysqlValueDecoded;
+use serde::{Deserialize, Serialize};
+use serde_yaml::with::singleton_map;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+
+/// Files already loaded, so a file used by several columns is read and kept once.
+static TABLES: LazyLock<Mutex<HashMap<PathBuf, Arc<LookupTable>>>> =
+    LazyLock::new(Default::default);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct LookupConfig {
+    file: PathBuf,
+    #[serde(default, with = "singleton_map")]
+    fallback: Fallback,
+}
+
+/// What to do with the values missing from the file.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "snake_case")]
+enum Fallback {
+    #[default]
+    Keep,
+    Nullify,
+    Default(Replacement),
+    /// One of the values of the file, always the same for a given value.
+    Pick,
+}
+
+/// Substitute values with the ones a file maps them to. The file is read once, when the
+/// configuration is, and shared by every column and table using it.
+///
+/// A mapping is a CSV file of two columns, key then value, or a YAML or JSON object. A
+/// list, in a CSV file of one column or a YAML or JSON array, maps nothing and only gives
+/// values to pick from.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "LookupConfig", into = "LookupConfig")]
+pub(crate) struct Lookup {
+    config: LookupConfig,
+    table: Arc<LookupTable>,
+}
+
+impl TryFrom<LookupConfig> for Lookup {
+    type Error = String;
+
+    fn try_from(config: LookupConfig) -> Result<Self, Self::Error> {
+        let table = {
+            let mut tables = TABLES.lock().unwrap();
+
+            match tables.get(&config.file) {
+                Some(table) => table.clone(),
+                None => {
+                    let table = Arc::new(LookupTable::load(&config.file)?);
+                    tables.insert(config.file.clone(), table.clone());
+
+                    table
+                }
+            }
+        };
+
+        if matches!(config.fallback, Fallback::Pick) && table.values.is_empty() {
+            return Err(format!(
+                "no value to pick from in {}",
+                config.file.display()
+            ));
+        }
+
+        Ok(Self { config, table })
+    }
+}
+
+impl From<Lookup> for LookupConfig {
+    fn from(lookup: Lookup) -> Self {
+        lookup.config
+    }
+}
+
+impl Lookup {
+    pub(crate) fn transform(&self, value: MysqlValueDecoded) -> MysqlValueDecoded {
+        let Some(key) = value.as_text() else {
+            return value;
+        };
+
+        if let Some(substitute) = self.table.entries.get(key.as_ref()) {
+            return match substitute {
+                Some(substitute) => text_like(value, substitute.clone()),
+                None => MysqlValueDecoded::Null,
+            };
+        }
+
+        match &self.config.fallback {
+            Fallback::Keep => value,
+            Fallback::Nullify => MysqlValueDecoded::Null,
+            Fallback::Default(replacement) => replacement.value().clone(),
+            Fallback::Pick => {
+                let digest = Sha256::digest(key.as_bytes());
+                let index = u64::from_be_bytes(digest[..8].try_into().unwrap())
+                    % self.table.values.len() as u64;
+                let picked = self.table.values[index as usize].clone();
+
+                text_like(value, picked)
+            }
+        }
+    }
+}
+
+struct LookupTable {
+    /// Substitutes by value, None standing for NULL.
+    entries: HashMap<String, Option<String>>,
+    /// Values to pick from, in the order of the file.
+    values: Vec<String>,
+}
+
+// A file can hold thousands of entries, too many for the logs.
+impl fmt::Debug for LookupTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LookupTable")
+            .field("entries", &self.entries.len())
+            .field("values", &self.values.len())
+            .finish()
+    }
+}
+
+impl LookupTable {
+    fn load(file: &Path) -> Result<Self, String> {
+        let error = |e: &dyn fmt::Display| format!("cannot read {}: {}", file.display(), e);
+
+        let extension = file
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase);
+
+        let document = match extension.as_deref() {
+            Some("csv") => return Self::load_csv(file).map_err(|e| error(&e)),
+            // JSON is YAML as well.
+            Some("yaml" | "yml" | "json") => {
+                let reader = std::fs::File::open(file).map_err(|e| error(&e))?;
+
+                serde_yaml::from_reader::<_, serde_yaml::Value>(reader).map_err(|e| error(&e))?
+            }
+            _ => {
+                return Err(format!(
+                    "unsupported lookup file {}, expected .csv, .yaml, .yml or .json",
+                    file.display()
+                ));
+            }
+        };
+
+        let mut table = Self {
+            entries: HashMap::new(),
+            values: Vec::new(),
+        };
+
+        match document {
+            serde_yaml::Value::Mapping(mapping) => {
+                for (key, value) in mapping.iter() {
+                    let key = scalar(key)
+                        .flatten()
+                        .ok_or_else(|| error(&format!("invalid key {:?}", key)))?;
+                    let value = scalar(value)
+                        .ok_or_else(|| error(&format!("invalid value {:?} of {}", value, key)))?;
+
+                    table.values.extend(value.clone());
+                    table.entries.insert(key, value);
+                }
+            }
+            serde_yaml::Value::Sequence(sequence) => {
+                for value in sequence.iter() {
+                    let value = scalar(value)
+                        .flatten()
+                        .ok_or_else(|| error(&format!("invalid value {:?}", value)))?;
+
+                    table.values.push(value);
+                }
+            }
+            _ => return Err(error(&"expected a mapping or a list")),
+        }
+
+        Ok(table)
+    }
+
+    fn load_csv(file: &Path) -> Result<Self, csv::Error> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_path(file)?;
+
+        let mut table = Self {
+            entries: HashMap::new(),
+            values: Vec::new(),
+        };
+
+        for record in reader.records() {
+            let record = record?;
+
+            match (record.get(0), record.get(1)) {
+                (Some(key), Some(value)) => {
+                    table.values.push(value.to_string());
+                    table
+                        .entries
+                        .insert(key.to_string(), Some(value.to_string()));
+                }
+                (Some(value), None) => table.values.push(value.to_string()),
+                _ => {}
+            }
+        }
+
+        Ok(table)
+    }
+}
+
+/// Text of a scalar of a YAML or JSON file, Some(None) for null.
+fn scalar(value: &serde_yaml::Value) -> Option<Option<String>> {
+    match value {
+        serde_yaml::Value::Null => Some(None),
+        serde_yaml::Value::Bool(b) => Some(Some(b.to_string())),
+        serde_yaml::Value::Number(n) => Some(Some(n.to_string())),
+        serde_yaml::Value::String(s) => Some(Some(s.clone())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lookup over a file written with `content`, named after the test since loaded files
+    /// are kept by path.
+    fn lookup(name: &str, content: &str, fallback: &str) -> Lookup {
+        let file = std::env::temp_dir().join(format!("lookup-{}-{}", std::process::id(), name));
+        std::fs::write(&file, content).unwrap();
+
+        serde_yaml::from_str(&format!(
+            "file: {}\nfallback: {}\n",
+            file.display(),
+            fallback
+        ))
+        .unwrap()
+    }
+
+    fn text(value: MysqlValueDecoded) -> Option<String> {
+        match value {
+            MysqlValueDecoded::Null => None,
+            MysqlValueDecoded::String(text) => Some(text),
+            value => panic!("unexpected value {:?}", value),
+        }
+    }
+
+    fn transform(lookup: &Lookup, value: &str) -> Option<String> {
+        text(lookup.transform(MysqlValueDecoded::String(value.to_string())))
+    }
+
+    #[test]
+    fn csv_maps_keys_to_values() {
+        let lookup = lookup(
+            "mapping.csv",
+            "Acme,Globex\n\"Smith, Jones\",Initech\n",
+            "keep",
+        );
+
+        assert_eq!(transform(&lookup, "Acme").as_deref(), Some("Globex"));
+        assert_eq!(
+            transform(&lookup, "Smith, Jones").as_deref(),
+            Some("Initech")
+        );
+        assert_eq!(transform(&lookup, "Umbrella").as_deref(), Some("Umbrella"));
+    }
+
+    #[test]
+    fn yaml_maps_keys_to_values_and_null() {
+        let lookup = lookup("mapping.yaml", "FR: BE\n42: 43\nXX: ~\n", "nullify");
+
+        assert_eq!(transform(&lookup, "FR").as_deref(), Some("BE"));
+        assert_eq!(transform(&lookup, "42").as_deref(), Some("43"));
+        assert_eq!(transform(&lookup, "XX"), None);
+        assert_eq!(transform(&lookup, "DE"), None);
+    }
+
+    #[test]
+    fn missing_keys_fall_back_to_the_default() {
+        let lookup = lookup("mapping.json", "{\"FR\": \"BE\"}", "\n  default: ZZ");
+
+        assert_eq!(transform(&lookup, "FR").as_deref(), Some("BE"));
+        assert_eq!(transform(&lookup, "DE").as_deref(), Some("ZZ"));
+    }
+
+    #[test]
+    fn pick_is_stable_and_from_the_file() {
+        let values = ["Globex", "Initech", "Umbrella", "Hooli"];
+        let lookup = lookup("values.csv", &values.join("\n"), "pick");
+
+        for key in ["Acme", "Wayne", "Stark", "Tyrell"] {
+            let picked = transform(&lookup, key).unwrap();
+
+            assert!(values.contains(&picked.as_str()));
+            assert_eq!(transform(&lookup, key).unwrap(), picked);
+        }
+    }
+
+    #[test]
+    fn pick_needs_values() {
+        let file = std::env::temp_dir().join(format!("lookup-{}-empty.yaml", std::process::id()));
+        std::fs::write(&file, "[]").unwrap();
+
+        let lookup =
+            serde_yaml::from_str::<Lookup>(&format!("file: {}\nfallback: pick\n", file.display()));
+
+        assert!(lookup.is_err());
+    }
+}
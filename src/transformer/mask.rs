@@ -0,0 +1,105 @@
+use crate::transformer::{Pattern, cannot_transform, text_like};
+use crate::value::MysqlValueDecoded;
+use serde::{Deserialize, Serialize};
+
+/// Replace every match of the pattern, the replacement referring to capture groups as `$1`
+/// or `${name}`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct RegexReplace {
+    pattern: Pattern,
+    replacement: String,
+}
+
+impl RegexReplace {
+    pub(crate) fn transform(&self, value: MysqlValueDecoded) -> MysqlValueDecoded {
+        let Some(text) = text_of(&value) else {
+            return not_text(value);
+        };
+
+        let replaced = self
+            .pattern
+            .replace_all(&text, self.replacement.as_str())
+            .into_owned();
+
+        text_like(value, replaced)
+    }
+}
+
+/// Hide the characters of the value behind a mask character, except the first and last
+/// ones asked for and the separators to preserve, which are not counted as characters to
+/// keep. A value too short to keep that many characters is masked entirely.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct Mask {
+    #[serde(default)]
+    keep_first: usize,
+    #[serde(default)]
+    keep_last: usize,
+    #[serde(default = "default_mask_char", rename = "char")]
+    mask_char: char,
+    /// Characters left as they are, like `@`, `.` or `-`.
+    #[serde(default)]
+    preserve: String,
+}
+
+fn default_mask_char() -> char {
+    '*'
+}
+
+impl Mask {
+    /// Whether masked numbers stay numbers.
+    pub(crate) fn yields_numbers(&self) -> bool {
+        self.mask_char.is_ascii_digit()
+    }
+
+    pub(crate) fn transform(&self, value: MysqlValueDecoded) -> MysqlValueDecoded {
+        let Some(text) = text_of(&value) else {
+            return not_text(value);
+        };
+
+        let maskable = text.chars().filter(|c| !self.preserve.contains(*c)).count();
+        let (keep_first, keep_last) = if self.keep_first + self.keep_last < maskable {
+            (self.keep_first, self.keep_last)
+        } else {
+            (0, 0)
+        };
+
+        let mut position = 0;
+        let masked = text
+            .chars()
+            .map(|c| {
+                if self.preserve.contains(c) {
+                    return c;
+                }
+
+                position += 1;
+
+                if position <= keep_first || position > maskable - keep_last {
+                    c
+                } else {
+                    self.mask_char
+                }
+            })
+            .collect();
+
+        text_like(value, masked)
+    }
+}
+
+/// Text to work on, None for NULL and binary values that are not text.
+fn text_of(value: &MysqlValueDecoded) -> Option<String> {
+    match value {
+        MysqlValueDecoded::Bytes(bytes) => std::str::from_utf8(bytes).ok().map(str::to_string),
+        value => value.as_text().map(|text| text.into_owned()),
+    }
+}
+
+/// Result for a value without text: NULL stays NULL, binary values which are not text
+/// cannot be masked.
+fn not_text(value: MysqlValueDecoded) -> MysqlValueDecoded {
+    match value {
+        MysqlValueDecoded::Null => value,
+        _ => cannot_transform(format_args!(
+            "cannot apply a text transformer to a binary value"
+        )),
+    }
+}
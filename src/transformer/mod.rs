@@ -0,0 +1,655 @@
+mod blob;
+mod condition;
+mod date;
+mod encrypt;
+mod faker;
+mod ip;
+mod json;
+mod lookup;
+mod mask;
+mod numeric;
+mod php;
+mod pseudonymize;
+mod replace;
+mod sample;
+mod script;
+mod template;
+mod unique;
+mod validate;
+
+use crate::value::MysqlValueDecoded;
+use blob::Truncate;
+use condition::Condition;
+use date::{DateShift, DateTruncate};
+use encrypt::Encrypt;
+use faker::Fake;
+use ip::IpAnonymize;
+use json::Json;
+use json_patch::{Patch, patch as json_patch};
+use lookup::Lookup;
+use mask::{Mask, RegexReplace};
+use numeric::{Bucket, Noise};
+use php::Php;
+use pseudonymize::Pseudonymize;
+use regex::Regex;
+use replace::Replacement;
+use script::Script;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_yaml::with::singleton_map;
+use serde_yaml::{Mapping, Value};
+use std::collections::HashMap;
+use std::ops::Deref;
+use template::Template;
+
+pub(crate) use encrypt::{Alphabet, Cipher, load_key};
+pub(crate) use sample::ColumnSamples;
+pub(crate) use unique::UniqueKeys;
+pub(crate) use validate::validate_transformers;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Transformer {
+    Replace(Replacement),
+    JsonPatch(Patch),
+    Nullify,
+    Pseudonymize(Pseudonymize),
+    Fake(Fake),
+    Template(Template),
+    #[serde(rename = "regex_replace")]
+    RegexReplace(RegexReplace),
+    Mask(Mask),
+    Script(Script),
+    #[serde(rename = "date_shift")]
+    DateShift(DateShift),
+    #[serde(rename = "date_truncate")]
+    DateTruncate(DateTruncate),
+    Noise(Noise),
+    Bucket(Bucket),
+    Json(Json),
+    Php(Php),
+    Lookup(Lookup),
+    Encrypt(Encrypt),
+    Shuffle,
+    #[serde(rename = "sample_from_column")]
+    SampleFromColumn,
+    #[serde(rename = "ip_anonymize")]
+    IpAnonymize(IpAnonymize),
+    Placeholder,
+    Truncate(Truncate),
+    #[serde(rename = "content_hash")]
+    ContentHash,
+}
+
+/// A transformer of a column, applied only to the rows matching its `when` condition.
+///
+/// Written as the transformer itself with an optional `when` key next to it, so parsed
+/// through a YAML value: the transformer enum cannot share its map with another field.
+#[derive(Debug, Clone)]
+pub(crate) struct TransformerConfig {
+    transformer: Transformer,
+    when: Option<Condition>,
+}
+
+impl<'de> Deserialize<'de> for TransformerConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        // Transformers may also be written as a tag, like `!replace 3`: the same as a map of
+        // the transformer to its options.
+        let mut value = match Value::deserialize(deserializer)? {
+            Value::Tagged(tagged) => {
+                let name = tagged.tag.to_string();
+                let name = name.trim_start_matches('!');
+
+                Value::Mapping(Mapping::from_iter([(Value::from(name), tagged.value)]))
+            }
+            value => value,
+        };
+        let when = match &mut value {
+            Value::Mapping(mapping) => mapping.remove("when"),
+            _ => None,
+        };
+
+        // Transformers without options, like `nullify`, are a bare name. Next to `when` they
+        // are a key without value.
+        let transformer = singleton_map::deserialize(value.clone()).or_else(|e| match value {
+            Value::Mapping(mapping) if mapping.len() == 1 => match mapping.into_iter().next() {
+                Some((name, Value::Null)) => singleton_map::deserialize(name),
+                _ => Err(e),
+            },
+            _ => Err(e),
+        });
+
+        Ok(Self {
+            transformer: transformer.map_err(D::Error::custom)?,
+            when: when
+                .map(Condition::deserialize)
+                .transpose()
+                .map_err(D::Error::custom)?,
+        })
+    }
+}
+
+impl Serialize for TransformerConfig {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::Error;
+
+        let value = singleton_map::serialize(&self.transformer, serde_yaml::value::Serializer)
+            .map_err(S::Error::custom)?;
+
+        let Some(when) = &self.when else {
+            return value.serialize(serializer);
+        };
+
+        let mut mapping = match value {
+            Value::Mapping(mapping) => mapping,
+            name => Mapping::from_iter([(name, Value::Null)]),
+        };
+        mapping.insert(
+            Value::from("when"),
+            serde_yaml::to_value(when).map_err(S::Error::custom)?,
+        );
+
+        mapping.serialize(serializer)
+    }
+}
+
+/// Transformers of a column, applied one after the other to the value. Either a list or, as
+/// most columns need only one, a single transformer.
+#[derive(Debug, Clone)]
+pub(crate) struct TransformerPipeline(Vec<TransformerConfig>);
+
+impl<'de> Deserialize<'de> for TransformerPipeline {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let steps = match Value::deserialize(deserializer)? {
+            Value::Sequence(steps) => steps
+                .into_iter()
+                .map(TransformerConfig::deserialize)
+                .collect::<Result<_, _>>(),
+            step => TransformerConfig::deserialize(step).map(|step| vec![step]),
+        };
+
+        steps.map(Self).map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for TransformerPipeline {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0.as_slice() {
+            [step] => step.serialize(serializer),
+            steps => steps.serialize(serializer),
+        }
+    }
+}
+
+impl TransformerPipeline {
+    /// Whether the transformers draw from the values of the column, which must then be read
+    /// before the rows.
+    pub(crate) fn needs_samples(&self) -> bool {
+        self.0.iter().any(|step| {
+            matches!(
+                step.transformer,
+                Transformer::Shuffle | Transformer::SampleFromColumn
+            )
+        })
+    }
+
+    /// Value every row gets, when the last transformer replaces it whatever the row.
+    fn constant(&self) -> Option<&MysqlValueDecoded> {
+        match self.0.last()? {
+            TransformerConfig {
+                transformer: Transformer::Replace(replacement),
+                when: None,
+            } => Some(replacement.value()),
+            _ => None,
+        }
+    }
+
+    /// Whether the transformers read the value `column` is given by its own transformers,
+    /// which must then run first.
+    fn reads(&self, column: &str) -> bool {
+        self.0.iter().any(|step| match &step.transformer {
+            Transformer::Template(template) => template.reads(column),
+            Transformer::Script(script) => script.reads(column),
+            Transformer::Json(json) => json.reads(column),
+            Transformer::Php(php) => php.reads(column),
+            _ => false,
+        })
+    }
+
+    /// Apply the transformers to a value of the column being transformed, or to one of its
+    /// nodes like those of a JSON document, their conditions testing the row.
+    fn apply(&self, mut value: MysqlValueDecoded, row: &RowContext) -> MysqlValueDecoded {
+        for step in self.0.iter() {
+            if let Some(when) = &step.when
+                && !when.evaluate(row.column, row)
+            {
+                continue;
+            }
+
+            value = step.transformer.transform(value, row);
+        }
+
+        value
+    }
+}
+
+/// Transformers of the nodes of a structured value, like a JSON document, keyed in the
+/// configuration by the path selecting them. Paths keep the order of the configuration.
+fn path_pipelines<P>(
+    paths: &Mapping,
+    parse: impl Fn(&str) -> Result<P, String>,
+) -> Result<Vec<(P, TransformerPipeline)>, String> {
+    paths
+        .iter()
+        .map(|(path, pipeline)| {
+            let path = path
+                .as_str()
+                .ok_or_else(|| format!("path {:?} is not a string", path))?;
+            let pipeline = TransformerPipeline::deserialize(pipeline.clone())
+                .map_err(|e| format!("{}: {}", path, e))?;
+
+            // Samples are values of whole columns, none are read for the nodes of a value.
+            if pipeline.needs_samples() {
+                return Err(format!(
+                    "{}: shuffle and sample_from_column only apply to whole columns",
+                    path
+                ));
+            }
+
+            Ok((parse(path)?, pipeline))
+        })
+        .collect()
+}
+
+/// Regular expression of the configuration, compiled once when it is read.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "String", into = "String")]
+pub(crate) struct Pattern(Regex);
+
+impl TryFrom<String> for Pattern {
+    type Error = regex::Error;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        Regex::new(&pattern).map(Pattern)
+    }
+}
+
+impl From<Pattern> for String {
+    fn from(pattern: Pattern) -> Self {
+        pattern.0.as_str().to_string()
+    }
+}
+
+impl Deref for Pattern {
+    type Target = Regex;
+
+    fn deref(&self) -> &Regex {
+        &self.0
+    }
+}
+
+/// The row a value belongs to, for transformers building it from other columns.
+pub(crate) struct RowContext<'a> {
+    /// Column being transformed.
+    column: &'a str,
+    fields: &'a [String],
+    original: &'a [MysqlValueDecoded],
+    current: &'a [MysqlValueDecoded],
+    /// Positions of the columns whose transformers have not run yet.
+    pending: &'a [usize],
+    samples: &'a ColumnSamples,
+}
+
+impl RowContext<'_> {
+    /// Value of the column as read from the source.
+    pub(crate) fn original(&self, column: &str) -> Option<&MysqlValueDecoded> {
+        let index = self.fields.iter().position(|field| field == column)?;
+
+        self.original.get(index)
+    }
+
+    /// Value of the column as transformed, None when its transformers have not run yet (the
+    /// column being transformed included): its source value must not end up elsewhere.
+    pub(crate) fn current(&self, column: &str) -> Option<&MysqlValueDecoded> {
+        let index = self.fields.iter().position(|field| field == column)?;
+
+        if self.pending.contains(&index) {
+            return None;
+        }
+
+        self.current.get(index)
+    }
+}
+
+/// Positions of the columns with transformers, in the order to transform them: a column
+/// comes after the ones whose transformed value its transformers read, in the order of the
+/// table otherwise. Fails when columns read each other.
+pub(crate) fn transform_order(
+    transformers: &HashMap<String, TransformerPipeline>,
+    fields: &[String],
+) -> Result<Vec<usize>, String> {
+    let mut pending = fields
+        .iter()
+        .enumerate()
+        .filter(|(_, field)| transformers.contains_key(*field))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    let mut order = Vec::with_capacity(pending.len());
+
+    while !pending.is_empty() {
+        let ready = pending.iter().position(|&i| {
+            pending
+                .iter()
+                .all(|&j| j == i || !transformers[&fields[i]].reads(&fields[j]))
+        });
+
+        match ready {
+            Some(position) => order.push(pending.remove(position)),
+            None => {
+                return Err(format!(
+                    "transformers of {} read each other's transformed value",
+                    pending
+                        .iter()
+                        .map(|&i| fields[i].as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+        }
+    }
+
+    Ok(order)
+}
+
+/// Apply the transformers of a table to a row, `fields` naming its columns in order,
+/// `order` the columns to transform as given by [transform_order] and `samples` holding the
+/// values read beforehand for the columns which need them.
+pub(crate) fn transform_row(
+    transformers: &HashMap<String, TransformerPipeline>,
+    fields: &[String],
+    order: &[usize],
+    samples: &ColumnSamples,
+    mut row: Vec<MysqlValueDecoded>,
+) -> Vec<MysqlValueDecoded> {
+    if transformers.is_empty() {
+        return row;
+    }
+
+    let original = row.clone();
+
+    for (position, &i) in order.iter().enumerate() {
+        let field = &fields[i];
+        let Some(pipeline) = transformers.get(field) else {
+            continue;
+        };
+
+        if i >= row.len() {
+            continue;
+        }
+
+        // The column itself counts as pending: its value in the row is the source one until
+        // the whole pipeline ran.
+        let context = RowContext {
+            column: field,
+            fields,
+            original: &original,
+            current: &row,
+            pending: &order[position..],
+            samples,
+        };
+        row[i] = pipeline.apply(row[i].clone(), &context);
+
+        // Generated text which is not a number: MySQL would store it in the numeric column
+        // as 0, or as the number it starts with.
+        if let MysqlValueDecoded::String(text) = &row[i]
+            && text.trim().parse::<f64>().is_err()
+            && matches!(
+                original[i],
+                MysqlValueDecoded::Int(_)
+                    | MysqlValueDecoded::UInt(_)
+                    | MysqlValueDecoded::Double(_)
+                    | MysqlValueDecoded::Decimal(_)
+            )
+        {
+            tracing::warn!("value of {} is not a number, set to NULL", field);
+
+            row[i] = MysqlValueDecoded::Null;
+        }
+    }
+
+    row
+}
+
+/// Key material of a transformer, given either in the configuration or, to keep it out of
+/// the file, through an environment variable.
+fn resolve_secret(secret: Option<&str>, secret_env: Option<&str>) -> Result<Vec<u8>, String> {
+    match (secret, secret_env) {
+        (Some(secret), None) => Ok(secret.as_bytes().to_vec()),
+        (None, Some(secret_env)) => std::env::var(secret_env)
+            .map(String::into_bytes)
+            .map_err(|e| format!("cannot read secret from {}: {}", secret_env, e)),
+        (Some(_), Some(_)) => Err("secret and secret_env are mutually exclusive".to_string()),
+        (None, None) => Err("either secret or secret_env is required".to_string()),
+    }
+}
+
+/// Bytes written as hexadecimal, None when the text is not.
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Generated text keeps the type of the column where it can: binary columns stay binary,
+/// numeric ones numeric when the text is a number.
+fn text_like(original: MysqlValueDecoded, text: String) -> MysqlValueDecoded {
+    match original {
+        MysqlValueDecoded::Bytes(_) => MysqlValueDecoded::Bytes(text.into_bytes()),
+        MysqlValueDecoded::Int(_) if let Ok(i) = text.parse() => MysqlValueDecoded::Int(i),
+        MysqlValueDecoded::UInt(_) if let Ok(u) = text.parse() => MysqlValueDecoded::UInt(u),
+        MysqlValueDecoded::Double(_) if let Ok(f) = text.parse() => MysqlValueDecoded::Double(f),
+        MysqlValueDecoded::Decimal(_) if let Ok(d) = text.parse() => MysqlValueDecoded::Decimal(d),
+        _ => MysqlValueDecoded::String(text),
+    }
+}
+
+/// What a transformer gives for a value it cannot work on: NULL, never the value itself,
+/// which would copy the source data as is into the anonymized database. Logged without the
+/// value, for the same reason.
+fn cannot_transform(reason: std::fmt::Arguments) -> MysqlValueDecoded {
+    tracing::warn!("{}, set to NULL", reason);
+
+    MysqlValueDecoded::Null
+}
+
+impl Transformer {
+    pub(crate) fn transform(
+        &self,
+        value: MysqlValueDecoded,
+        row: &RowContext,
+    ) -> MysqlValueDecoded {
+        match (self, value) {
+            (Transformer::Replace(replacement), _) => replacement.value().clone(),
+            (Transformer::Nullify, _) => MysqlValueDecoded::Null,
+            (Transformer::Pseudonymize(pseudonymize), value) => pseudonymize.transform(value),
+            (Transformer::Fake(fake), value) => fake.transform(value),
+            (Transformer::Template(template), value) => template.transform(value, row),
+            (Transformer::RegexReplace(regex_replace), value) => regex_replace.transform(value),
+            (Transformer::Mask(mask), value) => mask.transform(value),
+            (Transformer::Script(script), value) => script.transform(value, row),
+            (Transformer::DateShift(date_shift), value) => date_shift.transform(value, row),
+            (Transformer::DateTruncate(date_truncate), value) => date_truncate.transform(value),
+            (Transformer::Noise(noise), value) => noise.transform(value),
+            (Transformer::Bucket(bucket), value) => bucket.transform(value),
+            (Transformer::Json(json), value) => json.transform(value, row),
+            (Transformer::Php(php), value) => php.transform(value, row),
+            (Transformer::Lookup(lookup), value) => lookup.transform(value),
+            (Transformer::Encrypt(encrypt), value) => encrypt.transform(value),
+            (Transformer::Shuffle, _) => match row.samples.shuffled(row.column) {
+                Some(shuffled) => shuffled,
+                None => {
+                    cannot_transform(format_args!("no value of {} left to shuffle", row.column))
+                }
+            },
+            (Transformer::IpAnonymize(ip_anonymize), value) => ip_anonymize.transform(value),
+            (Transformer::Placeholder, value) => blob::placeholder(value),
+            (Transformer::Truncate(truncate), value) => truncate.transform(value),
+            (Transformer::ContentHash, value) => blob::content_hash(value),
+            (Transformer::SampleFromColumn, _) => match row.samples.sample(row.column) {
+                Some(sample) => sample,
+                None => cannot_transform(format_args!("no value of {} to sample", row.column)),
+            },
+            (Transformer::JsonPatch(patch), MysqlValueDecoded::String(json)) => {
+                // decode json to merge
+                let mut json_value: serde_json::Value = match serde_json::from_str(json.as_str()) {
+                    Ok(value) => value,
+                    Err(e) => return cannot_transform(format_args!("failed to decode json {}", e)),
+                };
+
+                if let Err(e) = json_patch(&mut json_value, patch) {
+                    return cannot_transform(format_args!("failed to apply json patch {}", e));
+                }
+
+                let json_string = match serde_json::to_string(&json_value) {
+                    Ok(value) => value,
+                    Err(e) => return cannot_transform(format_args!("failed to encode json {}", e)),
+                };
+
+                MysqlValueDecoded::String(json_string)
+            }
+            (Transformer::JsonPatch(patch), MysqlValueDecoded::Bytes(json)) => {
+                // decode json to merge
+                let mut json_value: serde_json::Value =
+                    match serde_json::from_slice(json.as_slice()) {
+                        Ok(value) => value,
+                        Err(e) => {
+                            return cannot_transform(format_args!("failed to decode json {}", e));
+                        }
+                    };
+
+                if let Err(e) = json_patch(&mut json_value, patch) {
+                    return cannot_transform(format_args!("failed to apply json patch {}", e));
+                }
+
+                let json_string = match serde_json::to_vec(&json_value) {
+                    Ok(value) => value,
+                    Err(e) => return cannot_transform(format_args!("failed to encode json {}", e)),
+                };
+
+                MysqlValueDecoded::Bytes(json_string)
+            }
+            (_, MysqlValueDecoded::Null) => MysqlValueDecoded::Null,
+            (Transformer::JsonPatch(_), _) => {
+                cannot_transform(format_args!("json_patch only applies to text"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn transformers(config: &str) -> HashMap<String, TransformerPipeline> {
+        serde_yaml::from_str(config).unwrap()
+    }
+
+    fn text(text: &str) -> MysqlValueDecoded {
+        MysqlValueDecoded::String(text.to_string())
+    }
+
+    #[test]
+    fn columns_read_by_a_template_are_transformed_first() {
+        let transformers = transformers(
+            "full_name: !template \"{{first_name}} {{last_name}}\"\nfirst_name: !replace Jane\nlast_name: !replace Doe\n",
+        );
+        let fields = fields(&["id", "full_name", "first_name", "last_name"]);
+
+        let order = transform_order(&transformers, &fields).unwrap();
+        assert_eq!(order, [2, 3, 1]);
+
+        let row = transform_row(
+            &transformers,
+            &fields,
+            &order,
+            &ColumnSamples::default(),
+            vec![
+                MysqlValueDecoded::Int(1),
+                text("John Smith"),
+                text("John"),
+                text("Smith"),
+            ],
+        );
+
+        match &row[1] {
+            MysqlValueDecoded::String(full_name) => assert_eq!(full_name, "Jane Doe"),
+            value => panic!("unexpected value {:?}", value),
+        }
+    }
+
+    #[test]
+    fn scripts_never_see_source_values_of_columns_transformed_later() {
+        let transformers =
+            transformers("a: !script 'row[\"ab\".sub_string(1)]'\nb: !script 'row.a'\n");
+        let fields = fields(&["a", "b"]);
+
+        let order = transform_order(&transformers, &fields).unwrap();
+        assert_eq!(order, [0, 1]);
+
+        let row = transform_row(
+            &transformers,
+            &fields,
+            &order,
+            &ColumnSamples::default(),
+            vec![text("secret a"), text("secret b")],
+        );
+
+        assert!(matches!(row[0], MysqlValueDecoded::Null));
+        assert!(matches!(row[1], MysqlValueDecoded::Null));
+    }
+
+    #[test]
+    fn values_transformers_cannot_work_on_are_set_to_null() {
+        let transformers = transformers(
+            "a: !script 'throw \"failed\"'\nb: !script 'loop {}'\nc: !json { paths: { $.a: nullify } }\nd: !mask {}\n",
+        );
+        let fields = fields(&["a", "b", "c", "d"]);
+        let order = transform_order(&transformers, &fields).unwrap();
+
+        let row = transform_row(
+            &transformers,
+            &fields,
+            &order,
+            &ColumnSamples::default(),
+            vec![
+                text("secret"),
+                text("secret"),
+                text("{secret"),
+                MysqlValueDecoded::Bytes(vec![0xff, 0xfe]),
+            ],
+        );
+
+        for value in row {
+            assert!(matches!(value, MysqlValueDecoded::Null));
+        }
+    }
+
+    #[test]
+    fn columns_reading_each_other_cannot_be_ordered() {
+        let transformers = transformers("a: !template \"{{b}}\"\nb: !template \"{{a}}\"\n");
+
+        assert!(transform_order(&transformers, &fields(&["a", "b"])).is_err());
+    }
+}
@@ -0,0 +1,163 @@
+use crate::transformer::cannot_transform;
+use crate::value::MysqlValueDecoded;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Decimal;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct NoiseConfig {
+    #[serde(default)]
+    amount: Option<f64>,
+    #[serde(default)]
+    percent: Option<f64>,
+}
+
+/// Add random noise to numbers: up to `amount` either way, or up to `percent` of the value.
+/// The sign of the value is kept, as is the scale of decimals; integers stay integers.
+///
+/// Values are only bounded by the 64-bit range, the column type is not known here: noise
+/// must leave room below the largest value of a narrower column (TINYINT, SMALLINT, INT).
+/// The target session is not strict, so a value out of the column range is silently stored
+/// as its nearest bound.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "NoiseConfig", into = "NoiseConfig")]
+pub(crate) struct Noise {
+    config: NoiseConfig,
+}
+
+impl TryFrom<NoiseConfig> for Noise {
+    type Error = String;
+
+    fn try_from(config: NoiseConfig) -> Result<Self, Self::Error> {
+        match (config.amount, config.percent) {
+            (Some(bound), None) | (None, Some(bound)) if bound >= 0.0 => Ok(Self { config }),
+            (Some(_), None) | (None, Some(_)) => Err("noise cannot be negative".to_string()),
+            _ => Err("noise needs one of amount or percent".to_string()),
+        }
+    }
+}
+
+impl From<Noise> for NoiseConfig {
+    fn from(noise: Noise) -> Self {
+        noise.config
+    }
+}
+
+impl Noise {
+    pub(crate) fn transform(&self, value: MysqlValueDecoded) -> MysqlValueDecoded {
+        let factor = rand::rng().random_range(-1.0..=1.0);
+        // Noise to add to a value, given as a float.
+        let delta = |magnitude: f64| match (self.config.amount, self.config.percent) {
+            (Some(amount), _) => amount * factor,
+            (_, Some(percent)) => magnitude * percent / 100.0 * factor,
+            _ => 0.0,
+        };
+
+        match value {
+            MysqlValueDecoded::Int(i) => {
+                let noisy = i.saturating_add(delta(i as f64).round() as i64);
+
+                MysqlValueDecoded::Int(if i >= 0 { noisy.max(0) } else { noisy.min(0) })
+            }
+            MysqlValueDecoded::UInt(u) => {
+                let delta = delta(u as f64).round() as i64;
+
+                MysqlValueDecoded::UInt(u.saturating_add_signed(delta))
+            }
+            MysqlValueDecoded::Double(f) => {
+                let noisy = f + delta(f);
+
+                MysqlValueDecoded::Double(if f >= 0.0 {
+                    noisy.max(0.0)
+                } else {
+                    noisy.min(0.0)
+                })
+            }
+            MysqlValueDecoded::Decimal(d) => {
+                let magnitude = d.to_string().parse().unwrap_or(0.0);
+                let noisy = Decimal::try_from(delta(magnitude))
+                    .ok()
+                    .and_then(|delta| d.checked_add(delta));
+                let Some(mut noisy) = noisy else {
+                    return cannot_transform(format_args!("cannot add noise to a decimal"));
+                };
+
+                if noisy.is_sign_negative() != d.is_sign_negative() {
+                    noisy = Decimal::ZERO;
+                }
+                noisy.rescale(d.scale());
+
+                MysqlValueDecoded::Decimal(noisy)
+            }
+            MysqlValueDecoded::Null => MysqlValueDecoded::Null,
+            _ => cannot_transform(format_args!("noise only applies to numbers")),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct BucketConfig {
+    size: f64,
+}
+
+/// Round numbers down to the lower bound of their range: with a size of 1000, any value from
+/// 3000 to 3999 becomes 3000.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "BucketConfig", into = "BucketConfig")]
+pub(crate) struct Bucket {
+    config: BucketConfig,
+}
+
+impl TryFrom<BucketConfig> for Bucket {
+    type Error = String;
+
+    fn try_from(config: BucketConfig) -> Result<Self, Self::Error> {
+        if config.size > 0.0 {
+            Ok(Self { config })
+        } else {
+            Err("bucket size must be positive".to_string())
+        }
+    }
+}
+
+impl From<Bucket> for BucketConfig {
+    fn from(bucket: Bucket) -> Self {
+        bucket.config
+    }
+}
+
+impl Bucket {
+    pub(crate) fn transform(&self, value: MysqlValueDecoded) -> MysqlValueDecoded {
+        let size = self.config.size;
+        let floor = |f: f64| (f / size).floor() * size;
+
+        match value {
+            MysqlValueDecoded::Int(i) if size.fract() == 0.0 => {
+                let size = size as i64;
+
+                MysqlValueDecoded::Int(i.div_euclid(size) * size)
+            }
+            MysqlValueDecoded::UInt(u) if size.fract() == 0.0 => {
+                let size = size as u64;
+
+                MysqlValueDecoded::UInt(u / size * size)
+            }
+            MysqlValueDecoded::Int(i) => MysqlValueDecoded::Int(floor(i as f64) as i64),
+            MysqlValueDecoded::UInt(u) => MysqlValueDecoded::UInt(floor(u as f64) as u64),
+            MysqlValueDecoded::Double(f) => MysqlValueDecoded::Double(floor(f)),
+            MysqlValueDecoded::Decimal(d) => {
+                let bucketed = Decimal::try_from(size)
+                    .ok()
+                    .and_then(|size| d.checked_div(size)?.floor().checked_mul(size));
+                let Some(mut bucketed) = bucketed else {
+                    return cannot_transform(format_args!("cannot bucket a decimal"));
+                };
+                bucketed.rescale(d.scale());
+
+                MysqlValueDecoded::Decimal(bucketed)
+            }
+            MysqlValueDecoded::Null => MysqlValueDecoded::Null,
+            _ => cannot_transform(format_args!("bucket only applies to numbers")),
+        }
+    }
+}
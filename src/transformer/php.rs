@@ -0,0 +1,626 @@
+use crate::transformer::{RowContext, TransformerPipeline, cannot_transform, path_pipelines};
+use crate::value::MysqlValueDecoded;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Mapping;
+
+/// Arrays and objects a payload may nest, as serde_json allows for JSON: parsing recurses
+/// for each of them, an unbounded payload would overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct PhpConfig {
+    /// Paths of the nodes to transform, each with its transformers.
+    paths: Mapping,
+    /// The payload went through `addslashes()` after `serialize()`, as Symfony Messenger
+    /// does before storing it.
+    #[serde(default)]
+    addslashes: bool,
+}
+
+/// Apply transformers to the nodes of a PHP `serialize()` payload, then serialize it again
+/// with the length prefixes matching the new values.
+///
+/// Paths are keys separated by dots, `*` matching any key: `user.email` or
+/// `contacts.*.email`. Keys of arrays are matched as written, integer ones included, and
+/// object properties by their name, whatever their visibility. Strings, numbers, booleans
+/// and null are given to the transformers as such, arrays and objects as their serialized
+/// form. Objects serialized by a custom `serialize()` method are opaque, and references
+/// are kept as they are. A payload which cannot be decoded is set to NULL.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "PhpConfig", into = "PhpConfig")]
+pub(crate) struct Php {
+    config: PhpConfig,
+    paths: Vec<(Vec<String>, TransformerPipeline)>,
+}
+
+impl TryFrom<PhpConfig> for Php {
+    type Error = String;
+
+    fn try_from(config: PhpConfig) -> Result<Self, Self::Error> {
+        let paths = path_pipelines(&config.paths, |path| {
+            if path.is_empty() {
+                return Err("empty php path".to_string());
+            }
+
+            Ok(path.split('.').map(str::to_string).collect())
+        })?;
+
+        Ok(Self { config, paths })
+    }
+}
+
+impl From<Php> for PhpConfig {
+    fn from(php: Php) -> Self {
+        php.config
+    }
+}
+
+impl Php {
+    /// Whether the transformers of the nodes read the transformed value of the column.
+    pub(crate) fn reads(&self, column: &str) -> bool {
+        self.paths
+            .iter()
+            .any(|(_, pipeline)| pipeline.reads(column))
+    }
+
+    pub(crate) fn transform(
+        &self,
+        value: MysqlValueDecoded,
+        row: &RowContext,
+    ) -> MysqlValueDecoded {
+        let payload = match &value {
+            MysqlValueDecoded::String(payload) => payload.as_bytes(),
+            MysqlValueDecoded::Bytes(payload) => payload.as_slice(),
+            MysqlValueDecoded::Null => return value,
+            _ => return cannot_transform(format_args!("php only applies to text")),
+        };
+
+        let payload = if self.config.addslashes {
+            strip_slashes(payload)
+        } else {
+            payload.to_vec()
+        };
+
+        let mut document = match PhpValue::parse(&payload) {
+            Ok(document) => document,
+            Err(e) => {
+                return cannot_transform(format_args!(
+                    "failed to decode php serialized value: {}",
+                    e
+                ));
+            }
+        };
+
+        for (path, pipeline) in self.paths.iter() {
+            document.visit(path, &mut |node| {
+                let transformed = pipeline.apply(node.to_value(), row);
+                *node = PhpValue::from_value(transformed, node);
+            });
+        }
+
+        let mut payload = Vec::new();
+        document.write(&mut payload);
+
+        if self.config.addslashes {
+            payload = add_slashes(&payload);
+        }
+
+        match value {
+            MysqlValueDecoded::String(_) => match String::from_utf8(payload) {
+                Ok(payload) => MysqlValueDecoded::String(payload),
+                Err(e) => MysqlValueDecoded::Bytes(e.into_bytes()),
+            },
+            _ => MysqlValueDecoded::Bytes(payload),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PhpValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    /// Kept as written, so untouched floats are serialized back identically.
+    Float(String),
+    String(Vec<u8>),
+    Array(Vec<(PhpValue, PhpValue)>),
+    Object {
+        class: Vec<u8>,
+        properties: Vec<(PhpValue, PhpValue)>,
+    },
+    /// Object of a class implementing `Serializable`, its data in its own format.
+    Custom {
+        class: Vec<u8>,
+        data: Vec<u8>,
+    },
+    Enum(Vec<u8>),
+    /// `r` or `R` reference to a value met before.
+    Reference(u8, u64),
+}
+
+impl PhpValue {
+    fn parse(payload: &[u8]) -> Result<PhpValue, String> {
+        let mut parser = Parser {
+            input: payload,
+            position: 0,
+            depth: 0,
+        };
+
+        let value = parser.value()?;
+
+        if parser.position != payload.len() {
+            return Err(format!("unexpected data at offset {}", parser.position));
+        }
+
+        Ok(value)
+    }
+
+    /// Call `f` on the nodes at the end of the path.
+    fn visit(&mut self, path: &[String], f: &mut impl FnMut(&mut PhpValue)) {
+        let Some((segment, rest)) = path.split_first() else {
+            f(self);
+
+            return;
+        };
+
+        let entries = match self {
+            PhpValue::Array(entries) => entries,
+            PhpValue::Object { properties, .. } => properties,
+            _ => return,
+        };
+
+        for (key, value) in entries.iter_mut() {
+            if segment == "*" || key.key_name().as_deref() == Some(segment.as_bytes()) {
+                value.visit(rest, f);
+            }
+        }
+    }
+
+    /// Name of an array key or an object property, the class or `*` prefixing the name of
+    /// private and protected properties left out.
+    fn key_name(&self) -> Option<Vec<u8>> {
+        match self {
+            PhpValue::Int(i) => Some(i.to_string().into_bytes()),
+            PhpValue::String(key) => match key.strip_prefix(b"\0") {
+                Some(rest) => rest
+                    .iter()
+                    .position(|&b| b == 0)
+                    .map(|end| rest[end + 1..].to_vec()),
+                None => Some(key.clone()),
+            },
+            _ => None,
+        }
+    }
+
+    fn to_value(&self) -> MysqlValueDecoded {
+        match self {
+            PhpValue::Null => MysqlValueDecoded::Null,
+            PhpValue::Bool(b) => MysqlValueDecoded::Bool(*b),
+            PhpValue::Int(i) => MysqlValueDecoded::Int(*i),
+            PhpValue::Float(f) => MysqlValueDecoded::Double(f.parse().unwrap_or(f64::NAN)),
+            PhpValue::String(s) => match String::from_utf8(s.clone()) {
+                Ok(s) => MysqlValueDecoded::String(s),
+                Err(e) => MysqlValueDecoded::Bytes(e.into_bytes()),
+            },
+            value => {
+                let mut serialized = Vec::new();
+                value.write(&mut serialized);
+
+                match String::from_utf8(serialized) {
+                    Ok(s) => MysqlValueDecoded::String(s),
+                    Err(e) => MysqlValueDecoded::Bytes(e.into_bytes()),
+                }
+            }
+        }
+    }
+
+    /// Node holding a transformed value: text replacing an array or an object is parsed
+    /// back when it is a serialized value.
+    fn from_value(value: MysqlValueDecoded, node: &PhpValue) -> PhpValue {
+        let structured = matches!(
+            node,
+            PhpValue::Array(_)
+                | PhpValue::Object { .. }
+                | PhpValue::Custom { .. }
+                | PhpValue::Enum(_)
+        );
+
+        match value {
+            MysqlValueDecoded::Null => PhpValue::Null,
+            MysqlValueDecoded::Bool(b) => PhpValue::Bool(b),
+            MysqlValueDecoded::Int(i) => PhpValue::Int(i),
+            MysqlValueDecoded::UInt(u) => match i64::try_from(u) {
+                Ok(i) => PhpValue::Int(i),
+                Err(_) => PhpValue::Float(u.to_string()),
+            },
+            MysqlValueDecoded::Double(f) => PhpValue::Float(format_float(f)),
+            MysqlValueDecoded::Decimal(d) => PhpValue::Float(d.to_string()),
+            value
+                if structured
+                    && let Some(serialized) = value.as_bytes()
+                    && let Ok(parsed) = PhpValue::parse(&serialized) =>
+            {
+                parsed
+            }
+            value => PhpValue::String(value.as_bytes().map_or(Vec::new(), |s| s.into_owned())),
+        }
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            PhpValue::Null => out.extend_from_slice(b"N;"),
+            PhpValue::Bool(b) => out.extend_from_slice(if *b { b"b:1;" } else { b"b:0;" }),
+            PhpValue::Int(i) => out.extend_from_slice(format!("i:{};", i).as_bytes()),
+            PhpValue::Float(f) => out.extend_from_slice(format!("d:{};", f).as_bytes()),
+            PhpValue::String(s) => {
+                out.extend_from_slice(b"s:");
+                write_quoted(s, out);
+                out.push(b';');
+            }
+            PhpValue::Array(entries) => {
+                out.extend_from_slice(format!("a:{}:", entries.len()).as_bytes());
+                write_entries(entries, out);
+            }
+            PhpValue::Object { class, properties } => {
+                out.extend_from_slice(b"O:");
+                write_quoted(class, out);
+                out.extend_from_slice(format!(":{}:", properties.len()).as_bytes());
+                write_entries(properties, out);
+            }
+            PhpValue::Custom { class, data } => {
+                out.extend_from_slice(b"C:");
+                write_quoted(class, out);
+                out.extend_from_slice(format!(":{}:{{", data.len()).as_bytes());
+                out.extend_from_slice(data);
+                out.push(b'}');
+            }
+            PhpValue::Enum(name) => {
+                out.extend_from_slice(b"E:");
+                write_quoted(name, out);
+                out.push(b';');
+            }
+            PhpValue::Reference(kind, index) => {
+                out.push(*kind);
+                out.extend_from_slice(format!(":{};", index).as_bytes());
+            }
+        }
+    }
+}
+
+/// `<length>:"<bytes>"`, the length being in bytes.
+fn write_quoted(s: &[u8], out: &mut Vec<u8>) {
+    out.extend_from_slice(format!("{}:\"", s.len()).as_bytes());
+    out.extend_from_slice(s);
+    out.push(b'"');
+}
+
+fn write_entries(entries: &[(PhpValue, PhpValue)], out: &mut Vec<u8>) {
+    out.push(b'{');
+    for (key, value) in entries {
+        key.write(out);
+        value.write(out);
+    }
+    out.push(b'}');
+}
+
+/// Floats as PHP writes them, with the special values it understands.
+fn format_float(f: f64) -> String {
+    if f.is_nan() {
+        "NAN".to_string()
+    } else if f.is_infinite() {
+        if f > 0.0 { "INF" } else { "-INF" }.to_string()
+    } else {
+        f.to_string()
+    }
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    position: usize,
+    /// Arrays and objects the parser is in.
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn value(&mut self) -> Result<PhpValue, String> {
+        let kind = self.next()?;
+
+        Ok(match kind {
+            b'N' => {
+                self.expect(b';')?;
+
+                PhpValue::Null
+            }
+            b'b' => {
+                self.expect(b':')?;
+
+                PhpValue::Bool(self.until(b';')? != b"0")
+            }
+            b'i' => {
+                self.expect(b':')?;
+
+                PhpValue::Int(self.number(b';')?)
+            }
+            b'd' => {
+                self.expect(b':')?;
+                let f = self.until(b';')?;
+
+                PhpValue::Float(String::from_utf8_lossy(f).into_owned())
+            }
+            b's' => {
+                self.expect(b':')?;
+                let s = self.quoted()?;
+                self.expect(b';')?;
+
+                PhpValue::String(s)
+            }
+            b'a' => {
+                self.expect(b':')?;
+                let count = self.number(b':')?;
+
+                PhpValue::Array(self.entries(count)?)
+            }
+            b'O' => {
+                self.expect(b':')?;
+                let class = self.quoted()?;
+                self.expect(b':')?;
+                let count = self.number(b':')?;
+
+                PhpValue::Object {
+                    class,
+                    properties: self.entries(count)?,
+                }
+            }
+            b'C' => {
+                self.expect(b':')?;
+                let class = self.quoted()?;
+                self.expect(b':')?;
+                let length = self.number(b':')?;
+                self.expect(b'{')?;
+                let data = self.take(length)?.to_vec();
+                self.expect(b'}')?;
+
+                PhpValue::Custom { class, data }
+            }
+            b'E' => {
+                self.expect(b':')?;
+                let name = self.quoted()?;
+                self.expect(b';')?;
+
+                PhpValue::Enum(name)
+            }
+            b'r' | b'R' => {
+                self.expect(b':')?;
+
+                PhpValue::Reference(kind, self.number(b';')?)
+            }
+            kind => {
+                return Err(format!(
+                    "unknown type {:?} at offset {}",
+                    kind as char,
+                    self.position - 1
+                ));
+            }
+        })
+    }
+
+    fn entries(&mut self, count: usize) -> Result<Vec<(PhpValue, PhpValue)>, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(format!(
+                "more than {} nested arrays and objects at offset {}",
+                MAX_DEPTH, self.position
+            ));
+        }
+
+        self.depth += 1;
+        self.expect(b'{')?;
+
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            let key = self.value()?;
+            if !matches!(key, PhpValue::Int(_) | PhpValue::String(_)) {
+                return Err(format!("invalid key before offset {}", self.position));
+            }
+
+            entries.push((key, self.value()?));
+        }
+
+        self.expect(b'}')?;
+        self.depth -= 1;
+
+        Ok(entries)
+    }
+
+    /// `<length>:"<bytes>"`
+    fn quoted(&mut self) -> Result<Vec<u8>, String> {
+        let length = self.number(b':')?;
+        self.expect(b'"')?;
+        let s = self.take(length)?.to_vec();
+        self.expect(b'"')?;
+
+        Ok(s)
+    }
+
+    fn number<T: std::str::FromStr>(&mut self, end: u8) -> Result<T, String> {
+        let start = self.position;
+        let digits = self.until(end)?;
+
+        std::str::from_utf8(digits)
+            .ok()
+            .and_then(|digits| digits.parse().ok())
+            .ok_or_else(|| format!("invalid number at offset {}", start))
+    }
+
+    /// Bytes up to `end`, which is consumed.
+    fn until(&mut self, end: u8) -> Result<&[u8], String> {
+        let length = self.input[self.position..]
+            .iter()
+            .position(|&b| b == end)
+            .ok_or_else(|| format!("missing {:?} after offset {}", end as char, self.position))?;
+
+        let start = self.position;
+        self.position += length + 1;
+
+        Ok(&self.input[start..start + length])
+    }
+
+    fn take(&mut self, length: usize) -> Result<&[u8], String> {
+        let input = self.input;
+        let bytes = input
+            .get(self.position..self.position + length)
+            .ok_or_else(|| "unexpected end of data".to_string())?;
+        self.position += length;
+
+        Ok(bytes)
+    }
+
+    fn next(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), String> {
+        let position = self.position;
+
+        match self.next()? {
+            b if b == expected => Ok(()),
+            b => Err(format!(
+                "expected {:?} at offset {}, found {:?}",
+                expected as char, position, b as char
+            )),
+        }
+    }
+}
+
+/// Reverse of PHP's `addslashes()`.
+fn strip_slashes(payload: &[u8]) -> Vec<u8> {
+    let mut stripped = Vec::with_capacity(payload.len());
+    let mut bytes = payload.iter();
+
+    while let Some(&b) = bytes.next() {
+        if b != b'\\' {
+            stripped.push(b);
+            continue;
+        }
+
+        match bytes.next() {
+            Some(b'0') => stripped.push(0),
+            Some(&escaped) => stripped.push(escaped),
+            None => {}
+        }
+    }
+
+    stripped
+}
+
+/// PHP's `addslashes()`: quotes, backslashes and NUL bytes escaped.
+fn add_slashes(payload: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(payload.len());
+
+    for &b in payload {
+        match b {
+            0 => escaped.extend_from_slice(b"\\0"),
+            b'\'' | b'"' | b'\\' => escaped.extend_from_slice(&[b'\\', b]),
+            b => escaped.push(b),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transformer::ColumnSamples;
+
+    const PAYLOAD: &str = "a:6:{s:4:\"user\";O:4:\"User\":3:{s:5:\"email\";s:11:\"a@b.example\";s:8:\"\0User\0id\";i:42;s:7:\"\0*\0tags\";a:2:{i:0;s:1:\"x\";i:1;d:0.1;}}s:6:\"active\";b:1;s:4:\"note\";N;s:4:\"same\";r:2;s:6:\"status\";E:13:\"Status:Active\";s:6:\"custom\";C:3:\"Foo\":5:{hello}}";
+
+    fn php(config: &str) -> Php {
+        serde_yaml::from_str(config).unwrap()
+    }
+
+    fn transform(php: &Php, payload: &str) -> String {
+        let samples = ColumnSamples::default();
+        let row = RowContext {
+            column: "payload",
+            fields: &[],
+            original: &[],
+            current: &[],
+            pending: &[],
+            samples: &samples,
+        };
+
+        match php.transform(MysqlValueDecoded::String(payload.to_string()), &row) {
+            MysqlValueDecoded::String(payload) => payload,
+            value => panic!("unexpected value {:?}", value),
+        }
+    }
+
+    #[test]
+    fn untouched_payload_is_written_back_identically() {
+        let php = php("paths:\n  missing: nullify\n");
+
+        assert_eq!(transform(&php, PAYLOAD), PAYLOAD);
+    }
+
+    #[test]
+    fn lengths_count_bytes_of_multibyte_replacements() {
+        let php = php("paths:\n  user.email:\n    replace: \"é@ü.fr\"\n");
+
+        assert_eq!(
+            transform(&php, PAYLOAD),
+            PAYLOAD.replace("s:11:\"a@b.example\"", "s:8:\"é@ü.fr\"")
+        );
+    }
+
+    #[test]
+    fn properties_are_matched_whatever_their_visibility() {
+        let php = php("paths:\n  user.id:\n    replace: 7\n  user.tags.*: nullify\n");
+
+        assert_eq!(
+            transform(&php, PAYLOAD),
+            PAYLOAD
+                .replace("i:42;", "i:7;")
+                .replace("i:0;s:1:\"x\";i:1;d:0.1;", "i:0;N;i:1;N;")
+        );
+    }
+
+    #[test]
+    fn addslashes_payload_is_unescaped_then_escaped_again() {
+        let php = php("addslashes: true\npaths:\n  email:\n    replace: \"o'neil@example.com\"\n");
+        let payload =
+            r#"a:2:{s:5:\"email\";s:11:\"a@b.example\";s:8:\"\0*\0owner\";s:7:\"O\'Brien\";}"#;
+
+        assert_eq!(
+            transform(&php, payload),
+            r#"a:2:{s:5:\"email\";s:18:\"o\'neil@example.com\";s:8:\"\0*\0owner\";s:7:\"O\'Brien\";}"#
+        );
+    }
+
+    #[test]
+    fn deeply_nested_payload_is_refused() {
+        let nested = |depth| format!("{}N;{}", "a:1:{i:0;".repeat(depth), "}".repeat(depth));
+
+        assert!(PhpValue::parse(nested(100_000).as_bytes()).is_err());
+        assert!(PhpValue::parse(nested(MAX_DEPTH).as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn invalid_payload_is_set_to_null() {
+        let php = php("paths:\n  email: nullify\n");
+        let samples = ColumnSamples::default();
+        let row = RowContext {
+            column: "payload",
+            fields: &[],
+            original: &[],
+            current: &[],
+            pending: &[],
+            samples: &samples,
+        };
+        let payload = "a:1:{s:5:\"email\";s:99:\"a@b.example\";}";
+
+        assert!(matches!(
+            php.transform(MysqlValueDecoded::String(payload.to_string()), &row),
+            MysqlValueDecoded::Null
+        ));
+    }
+}
@@ -0,0 +1,189 @@
+use crate::transformer::{cannot_transform, resolve_secret, text_like};
+use crate::value::MysqlValueDecoded;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fmt;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum PseudonymFormat {
+    /// Hexadecimal digest, `length` characters long.
+    #[default]
+    Hex,
+    /// `<hexadecimal digest>@<domain>`.
+    Email,
+    /// As many digits as the original value. Integers stay within the 64 bits of their
+    /// value, not the range of their column: the target clamps a value out of it to the
+    /// nearest bound (a 10 digits INT may get 9 999 999 999, stored as 2 147 483 647).
+    Numeric,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct PseudonymizeConfig {
+    #[serde(default)]
+    secret: Option<String>,
+    #[serde(default)]
+    secret_env: Option<String>,
+    #[serde(default)]
+    format: PseudonymFormat,
+    #[serde(default = "default_length")]
+    length: usize,
+    #[serde(default = "default_domain")]
+    domain: String,
+}
+
+fn default_length() -> usize {
+    16
+}
+
+fn default_domain() -> String {
+    "example.com".to_string()
+}
+
+/// Replace each distinct value by a pseudonym derived from an HMAC-SHA256 of the value:
+/// the same input always gives the same output, whatever the table or the run, as long as
+/// the secret stays the same, so joins on the column survive. Distinct values may still
+/// share a pseudonym, as the digest is cut to `length` or to the digits of the value: the
+/// rows colliding in a unique index are made unique again when copied. The original value
+/// cannot be recovered without the secret.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(try_from = "PseudonymizeConfig", into = "PseudonymizeConfig")]
+pub(crate) struct Pseudonymize {
+    config: PseudonymizeConfig,
+    key: Vec<u8>,
+}
+
+impl TryFrom<PseudonymizeConfig> for Pseudonymize {
+    type Error = String;
+
+    fn try_from(config: PseudonymizeConfig) -> Result<Self, Self::Error> {
+        let key = resolve_secret(config.secret.as_deref(), config.secret_env.as_deref())?;
+
+        Ok(Self { config, key })
+    }
+}
+
+impl From<Pseudonymize> for PseudonymizeConfig {
+    fn from(pseudonymize: Pseudonymize) -> Self {
+        pseudonymize.config
+    }
+}
+
+// The secret must not end up in the logs.
+impl fmt::Debug for Pseudonymize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pseudonymize")
+            .field("format", &self.config.format)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Pseudonymize {
+    /// Whether the pseudonyms are numbers, the only ones numeric columns can hold.
+    pub(crate) fn yields_numbers(&self) -> bool {
+        matches!(self.config.format, PseudonymFormat::Numeric)
+    }
+
+    pub(crate) fn transform(&self, value: MysqlValueDecoded) -> MysqlValueDecoded {
+        let Some(input) = value.as_bytes() else {
+            return value;
+        };
+
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts any key size");
+        mac.update(&input);
+        let digest = mac.finalize().into_bytes();
+
+        match self.config.format {
+            PseudonymFormat::Hex => {
+                let mut hex = to_hex(&digest);
+                hex.truncate(self.config.length);
+
+                text_like(value, hex)
+            }
+            PseudonymFormat::Email => {
+                let mut hex = to_hex(&digest);
+                hex.truncate(self.config.length);
+
+                text_like(value, format!("{}@{}", hex, self.config.domain))
+            }
+            PseudonymFormat::Numeric => numeric(value, &digest),
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn numeric(original: MysqlValueDecoded, digest: &[u8]) -> MysqlValueDecoded {
+    let seed = u128::from_be_bytes(digest[..16].try_into().unwrap());
+
+    // A number within [lower, upper) with as many digits as the original one, the upper
+    // bound being capped to what the value type (not the column) can hold.
+    let in_range = |magnitude: u128, max: u128| {
+        let digits = magnitude.checked_ilog10().unwrap_or(0) + 1;
+        let lower = if digits == 1 {
+            0
+        } else {
+            10u128.pow(digits - 1)
+        };
+        let upper = 10u128.pow(digits).min(max + 1);
+
+        lower + seed % (upper - lower)
+    };
+
+    match original {
+        MysqlValueDecoded::Int(i) => {
+            let max = if i < 0 {
+                i64::MIN.unsigned_abs() as u128
+            } else {
+                i64::MAX as u128
+            };
+            let magnitude = in_range(i.unsigned_abs() as u128, max);
+
+            MysqlValueDecoded::Int(if i < 0 {
+                -(magnitude as i128) as i64
+            } else {
+                magnitude as i64
+            })
+        }
+        MysqlValueDecoded::UInt(u) => {
+            MysqlValueDecoded::UInt(in_range(u as u128, u64::MAX as u128) as u64)
+        }
+        MysqlValueDecoded::String(s) => {
+            MysqlValueDecoded::String(digits(s.chars().count(), digest))
+        }
+        MysqlValueDecoded::Bytes(bytes) => {
+            MysqlValueDecoded::Bytes(digits(bytes.len(), digest).into_bytes())
+        }
+        MysqlValueDecoded::Null => MysqlValueDecoded::Null,
+        _ => cannot_transform(format_args!(
+            "numeric pseudonym not supported for the value"
+        )),
+    }
+}
+
+/// `length` digits drawn from the digest, chained through SHA-256 for long values.
+fn digits(length: usize, digest: &[u8]) -> String {
+    use sha2::Digest;
+
+    let mut out = String::with_capacity(length);
+    let mut block = digest.to_vec();
+
+    while out.len() < length {
+        for byte in block.iter() {
+            if out.len() == length {
+                break;
+            }
+
+            out.push(char::from(b'0' + byte % 10));
+        }
+
+        block = Sha256::digest(&block).to_vec();
+    }
+
+    out
+}
@@ -0,0 +1,96 @@
+use crate::transformer::from_hex;
+use crate::value::MysqlValueDecoded;
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use sqlx::types::Decimal;
+
+/// Constant replacing the value, converted once when the configuration is read.
+///
+/// Numbers become integers, unsigned ones beyond the signed range included, or doubles;
+/// sequences and mappings become their JSON text. Tags give the other types:
+/// `!decimal "12.50"`, `!datetime "2020-01-01 00:00:00"` (or a date alone), `!hex "CAFE"`
+/// for binary values and `!json` for a scalar to store as JSON text.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "Value", into = "Value")]
+pub(crate) struct Replacement {
+    source: Value,
+    value: MysqlValueDecoded,
+}
+
+impl TryFrom<Value> for Replacement {
+    type Error = String;
+
+    fn try_from(source: Value) -> Result<Self, Self::Error> {
+        let value = convert(&source)?;
+
+        Ok(Self { source, value })
+    }
+}
+
+impl From<Replacement> for Value {
+    fn from(replacement: Replacement) -> Self {
+        replacement.source
+    }
+}
+
+impl Replacement {
+    pub(crate) fn value(&self) -> &MysqlValueDecoded {
+        &self.value
+    }
+}
+
+fn convert(source: &Value) -> Result<MysqlValueDecoded, String> {
+    Ok(match source {
+        Value::Null => MysqlValueDecoded::Null,
+        Value::Bool(b) => MysqlValueDecoded::Bool(*b),
+        Value::Number(number) => {
+            if let Some(i) = number.as_i64() {
+                MysqlValueDecoded::Int(i)
+            } else if let Some(u) = number.as_u64() {
+                MysqlValueDecoded::UInt(u)
+            } else {
+                MysqlValueDecoded::Double(number.as_f64().unwrap_or(f64::NAN))
+            }
+        }
+        Value::String(s) => MysqlValueDecoded::String(s.clone()),
+        Value::Sequence(_) | Value::Mapping(_) => MysqlValueDecoded::String(to_json(source)?),
+        Value::Tagged(tagged) => {
+            let text = match &tagged.value {
+                Value::String(s) => Some(s.clone()),
+                Value::Number(n) => Some(n.to_string()),
+                _ => None,
+            };
+
+            match (tagged.tag.to_string().trim_start_matches('!'), text) {
+                ("decimal", Some(text)) => MysqlValueDecoded::Decimal(
+                    text.parse::<Decimal>()
+                        .map_err(|e| format!("invalid decimal {:?}: {}", text, e))?,
+                ),
+                ("datetime", Some(text)) => MysqlValueDecoded::DateTime(
+                    NaiveDateTime::parse_from_str(&text, "%Y-%m-%d %H:%M:%S%.f")
+                        .or_else(|_| {
+                            NaiveDate::parse_from_str(&text, "%Y-%m-%d")
+                                .map(|date| date.and_hms_opt(0, 0, 0).unwrap())
+                        })
+                        .map_err(|e| format!("invalid datetime {:?}: {}", text, e))?
+                        .and_utc(),
+                ),
+                ("hex", Some(text)) => MysqlValueDecoded::Bytes(
+                    from_hex(&text).ok_or_else(|| format!("invalid hexadecimal {:?}", text))?,
+                ),
+                ("json", _) => MysqlValueDecoded::String(to_json(&tagged.value)?),
+                (tag, _) => {
+                    return Err(format!(
+                        "unsupported replacement !{} {:?}",
+                        tag, tagged.value
+                    ));
+                }
+            }
+        }
+    })
+}
+
+fn to_json(value: &Value) -> Result<String, String> {
+    serde_json::to_string(value).map_err(|e| format!("cannot encode replacement as json: {}", e))
+}
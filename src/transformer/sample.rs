@@ -0,0 +1,63 @@
+use crate::value::MysqlValueDecoded;
+use rand::Rng;
+use rand::seq::SliceRandom;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Values of columns read before the rows of their table, for the transformers giving each
+/// row a value of the column taken from another row.
+#[derive(Default)]
+pub(crate) struct ColumnSamples {
+    columns: HashMap<String, ColumnSample>,
+}
+
+struct ColumnSample {
+    /// Every value of the column, in random order.
+    values: Vec<MysqlValueDecoded>,
+    /// Values of the shuffled column handed out so far, by the rows of every range.
+    taken: AtomicUsize,
+}
+
+impl ColumnSamples {
+    pub(crate) fn new(columns: HashMap<String, Vec<MysqlValueDecoded>>) -> Self {
+        let columns = columns
+            .into_iter()
+            .map(|(column, mut values)| {
+                values.shuffle(&mut rand::rng());
+
+                (
+                    column,
+                    ColumnSample {
+                        values,
+                        taken: AtomicUsize::new(0),
+                    },
+                )
+            })
+            .collect();
+
+        Self { columns }
+    }
+
+    /// The next value of a random permutation of the column: each value read goes to one
+    /// row, so the column holds the same values in the end, only on other rows.
+    pub(crate) fn shuffled(&self, column: &str) -> Option<MysqlValueDecoded> {
+        let sample = self.columns.get(column)?;
+        let index = sample.taken.fetch_add(1, Ordering::SeqCst);
+
+        sample.values.get(index).cloned()
+    }
+
+    /// A value of the column drawn at random, values appearing in many rows being drawn
+    /// as often.
+    pub(crate) fn sample(&self, column: &str) -> Option<MysqlValueDecoded> {
+        let sample = self.columns.get(column)?;
+
+        if sample.values.is_empty() {
+            return None;
+        }
+
+        let index = rand::rng().random_range(0..sample.values.len());
+
+        Some(sample.values[index].clone())
+    }
+}
@@ -0,0 +1,166 @@
+use crate::transformer::{RowContext, cannot_transform, text_like};
+use crate::value::MysqlValueDecoded;
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{AST, Dynamic, Engine, Map, Scope};
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+
+/// Operations a script may run for a single value, so a runaway loop fails instead of
+/// stalling the migration.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Nesting allowed in expressions (and in the bodies of functions), and depth of function
+/// calls, so a pathological script fails when compiled or run rather than overflowing the
+/// stack.
+const MAX_EXPR_DEPTH: usize = 64;
+const MAX_FUNCTION_EXPR_DEPTH: usize = 32;
+const MAX_CALL_LEVELS: usize = 32;
+
+/// Strings, arrays and maps a script may build, so it cannot exhaust the memory.
+const MAX_STRING_SIZE: usize = 1 << 20;
+const MAX_COLLECTION_SIZE: usize = 10_000;
+
+/// Scripts only compute: Rhai has no access to the network or the environment, and the
+/// module resolver, which would load `import`ed files from disk, is replaced by one finding
+/// nothing.
+static ENGINE: LazyLock<Engine> = LazyLock::new(|| {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_expr_depths(MAX_EXPR_DEPTH, MAX_FUNCTION_EXPR_DEPTH);
+    engine.set_max_call_levels(MAX_CALL_LEVELS);
+    engine.set_max_string_size(MAX_STRING_SIZE);
+    engine.set_max_array_size(MAX_COLLECTION_SIZE);
+    engine.set_max_map_size(MAX_COLLECTION_SIZE);
+    engine.disable_symbol("eval");
+    engine.on_print(|text| tracing::info!("script: {}", text));
+    engine.on_debug(|text, _, position| tracing::debug!("script {}: {}", position, text));
+
+    engine
+});
+
+/// Compute the value with a [Rhai](https://rhai.rs) script, compiled once when the
+/// configuration is read. The script gets the value in `value`, the row as transformed in
+/// `row` and as read from the source in `original`, and evaluates to the new value.
+///
+/// Columns named in the script are transformed before it. In `row`, the column itself (its
+/// value is in `value`) and the ones transformed after it are `()`, so reading them by a
+/// computed name never gives their source value.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "String", into = "String")]
+pub(crate) struct Script {
+    source: String,
+    ast: AST,
+}
+
+impl TryFrom<String> for Script {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        let ast = ENGINE
+            .compile(&source)
+            .map_err(|e| format!("cannot compile script {:?}: {}", source, e))?;
+
+        Ok(Self { source, ast })
+    }
+}
+
+impl From<Script> for String {
+    fn from(script: Script) -> Self {
+        script.source
+    }
+}
+
+impl Script {
+    /// Whether the script may read the transformed value of the column: its name appears in
+    /// the script as a word of its own.
+    pub(crate) fn reads(&self, column: &str) -> bool {
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+
+        self.source.match_indices(column).any(|(start, _)| {
+            let before = self.source[..start].chars().next_back();
+            let after = self.source[start + column.len()..].chars().next();
+
+            !before.is_some_and(is_word) && !after.is_some_and(is_word)
+        })
+    }
+
+    pub(crate) fn transform(
+        &self,
+        value: MysqlValueDecoded,
+        row: &RowContext,
+    ) -> MysqlValueDecoded {
+        let mut scope = Scope::new();
+        scope.push("value", to_dynamic(&value));
+        let current = row
+            .fields
+            .iter()
+            .map(|field| {
+                (
+                    field.into(),
+                    row.current(field).map_or(Dynamic::UNIT, to_dynamic),
+                )
+            })
+            .collect::<Map>();
+        scope.push("row", current);
+        scope.push("original", to_map(row.fields, row.original));
+
+        match ENGINE.eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast) {
+            Ok(result) => from_dynamic(value, result),
+            Err(e) => cannot_transform(format_args!(
+                "failed to run script {:?}: {}",
+                self.source, e
+            )),
+        }
+    }
+}
+
+fn to_map(fields: &[String], values: &[MysqlValueDecoded]) -> Map {
+    fields
+        .iter()
+        .zip(values)
+        .map(|(field, value)| (field.into(), to_dynamic(value)))
+        .collect()
+}
+
+/// Scripts get `()` for NULL, integers (unsigned ones beyond the signed range as text),
+/// floats, text for decimals and dates, and blobs for binary values.
+fn to_dynamic(value: &MysqlValueDecoded) -> Dynamic {
+    match value {
+        MysqlValueDecoded::Null => Dynamic::UNIT,
+        MysqlValueDecoded::Bool(b) => Dynamic::from(*b),
+        MysqlValueDecoded::Int(i) => Dynamic::from(*i),
+        MysqlValueDecoded::UInt(u) => match i64::try_from(*u) {
+            Ok(i) => Dynamic::from(i),
+            Err(_) => Dynamic::from(u.to_string()),
+        },
+        MysqlValueDecoded::Double(f) => Dynamic::from(*f),
+        MysqlValueDecoded::Bytes(bytes) => Dynamic::from_blob(bytes.clone()),
+        value => Dynamic::from(value.as_text().unwrap_or_default().into_owned()),
+    }
+}
+
+/// Text results take the type of the column as other generated text does, arrays and maps
+/// become JSON.
+fn from_dynamic(original: MysqlValueDecoded, result: Dynamic) -> MysqlValueDecoded {
+    if result.is_unit() {
+        MysqlValueDecoded::Null
+    } else if let Ok(b) = result.as_bool() {
+        MysqlValueDecoded::Bool(b)
+    } else if let Ok(i) = result.as_int() {
+        MysqlValueDecoded::Int(i)
+    } else if let Ok(f) = result.as_float() {
+        MysqlValueDecoded::Double(f)
+    } else if result.is_blob() {
+        MysqlValueDecoded::Bytes(result.cast())
+    } else if result.is_array() || result.is_map() {
+        match serde_json::to_string(&result) {
+            Ok(json) => text_like(original, json),
+            Err(e) => {
+                cannot_transform(format_args!("failed to encode script result as json {}", e))
+            }
+        }
+    } else {
+        text_like(original, result.to_string())
+    }
+}
@@ -0,0 +1,188 @@
+use crate::transformer::{RowContext, text_like};
+use crate::value::MysqlValueDecoded;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone)]
+enum Filter {
+    Lower,
+    Upper,
+    Slug,
+    /// Characters from `start`, at most `length` of them when given.
+    Substr(usize, Option<usize>),
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Text(String),
+    Column {
+        name: String,
+        original: bool,
+        filters: Vec<Filter>,
+    },
+}
+
+/// Build the value from a text where `{{column}}` is replaced by the value of another column
+/// of the row, as transformed (the columns a template reads are transformed before it), and
+/// `{{original.column}}` by its value in the source. Values go through the filters
+/// following them, as in `{{original.first_name | lower | substr(0, 1)}}`. NULL renders as
+/// an empty text.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "String", into = "String")]
+pub(crate) struct Template {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl TryFrom<String> for Template {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        let mut segments = Vec::new();
+        let mut rest = source.as_str();
+
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_string()));
+            }
+
+            let Some(end) = rest[start..].find("}}") else {
+                return Err(format!("unclosed {{{{ in template {:?}", source));
+            };
+
+            segments.push(parse_expression(&rest[start + 2..start + end])?);
+            rest = &rest[start + end + 2..];
+        }
+
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_string()));
+        }
+
+        Ok(Self { source, segments })
+    }
+}
+
+impl From<Template> for String {
+    fn from(template: Template) -> Self {
+        template.source
+    }
+}
+
+fn parse_expression(expression: &str) -> Result<Segment, String> {
+    let mut parts = expression.split('|').map(str::trim);
+    let column = parts.next().unwrap_or_default();
+
+    if column.is_empty() {
+        return Err(format!("missing column in {{{{{}}}}}", expression));
+    }
+
+    let (name, original) = match column.strip_prefix("original.") {
+        Some(name) => (name.to_string(), true),
+        None => (column.to_string(), false),
+    };
+
+    Ok(Segment::Column {
+        name,
+        original,
+        filters: parts.map(parse_filter).collect::<Result<_, _>>()?,
+    })
+}
+
+fn parse_filter(filter: &str) -> Result<Filter, String> {
+    let (name, arguments) = match filter.split_once('(') {
+        Some((name, arguments)) => {
+            let Some(arguments) = arguments.strip_suffix(')') else {
+                return Err(format!("unclosed ( in filter {:?}", filter));
+            };
+
+            let arguments = arguments
+                .split(',')
+                .map(|argument| argument.trim().parse::<usize>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("invalid argument in filter {:?}: {}", filter, e))?;
+
+            (name.trim(), arguments)
+        }
+        None => (filter, Vec::new()),
+    };
+
+    match (name, arguments.as_slice()) {
+        ("lower", []) => Ok(Filter::Lower),
+        ("upper", []) => Ok(Filter::Upper),
+        ("slug", []) => Ok(Filter::Slug),
+        ("substr", [start]) => Ok(Filter::Substr(*start, None)),
+        ("substr", [start, length]) => Ok(Filter::Substr(*start, Some(*length))),
+        _ => Err(format!("unknown filter {:?}", filter)),
+    }
+}
+
+impl Template {
+    /// Whether the template reads the transformed value of the column.
+    pub(crate) fn reads(&self, column: &str) -> bool {
+        self.segments.iter().any(|segment| {
+            matches!(segment, Segment::Column { name, original: false, .. } if name == column)
+        })
+    }
+
+    pub(crate) fn transform(
+        &self,
+        value: MysqlValueDecoded,
+        row: &RowContext,
+    ) -> MysqlValueDecoded {
+        let mut text = String::new();
+
+        for segment in self.segments.iter() {
+            match segment {
+                Segment::Text(part) => text.push_str(part),
+                Segment::Column {
+                    name,
+                    original,
+                    filters,
+                } => {
+                    let column = if *original {
+                        row.original(name)
+                    } else {
+                        row.current(name)
+                    };
+
+                    let Some(column) = column else {
+                        tracing::warn!(
+                            "unknown or not yet transformed column {} in template {:?}",
+                            name,
+                            self.source
+                        );
+                        continue;
+                    };
+
+                    let part = column.as_text().unwrap_or_default().into_owned();
+                    text.push_str(&filters.iter().fold(part, apply_filter));
+                }
+            }
+        }
+
+        text_like(value, text)
+    }
+}
+
+fn apply_filter(text: String, filter: &Filter) -> String {
+    match filter {
+        Filter::Lower => text.to_lowercase(),
+        Filter::Upper => text.to_uppercase(),
+        Filter::Slug => {
+            let ascii = deunicode::deunicode(&text).to_lowercase();
+
+            ascii
+                .split(|c: char| !c.is_ascii_alphanumeric())
+                .filter(|word| !word.is_empty())
+                .collect::<Vec<_>>()
+                .join("-")
+        }
+        Filter::Substr(start, length) => {
+            let chars = text.chars().skip(*start);
+
+            match length {
+                Some(length) => chars.take(*length).collect(),
+                None => chars.collect(),
+            }
+        }
+    }
+}
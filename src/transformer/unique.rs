@@ -0,0 +1,187 @@
+use crate::transformer::TransformerPipeline;
+use crate::value::MysqlValueDecoded;
+use chrono::TimeDelta;
+use sqlx::types::Decimal;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Mutex;
+
+/// Changes tried on a row before giving up, its insert then failing with a duplicate key.
+const MAX_ATTEMPTS: u32 = 1000;
+
+/// Unique indexes of a table covering transformed columns, with the keys of the rows
+/// transformed so far, so transformers giving the same value to several rows do not make
+/// the inserts fail with duplicate keys.
+///
+/// A row whose key is already taken gets the first transformed column of the index changed
+/// until the key is free: numbers and dates are moved forward, text gets a `_2`, `_3`...
+/// suffix, before the `@` of emails, shortened first if the column would not hold it. Keys
+/// are compared the way a case and accent insensitive collation would, and keys holding
+/// NULL never collide, as in MySQL.
+pub(crate) struct UniqueKeys {
+    indexes: Vec<UniqueIndex>,
+}
+
+struct UniqueIndex {
+    name: String,
+    /// Positions of the columns of the index in the row.
+    columns: Vec<usize>,
+    /// Position of the column changed when the key is taken.
+    adjusted: usize,
+    /// Characters (bytes for binary strings) the changed column holds, when limited.
+    max_length: Option<usize>,
+    /// Hashes of the keys seen, a false match only costing a needless change, with the
+    /// number of changes tried for each so far: the next row with the same key starts after
+    /// them instead of trying them all again.
+    seen: Mutex<HashMap<u64, u32>>,
+}
+
+impl UniqueKeys {
+    /// Keep the unique indexes, given by name with their columns, where a column is
+    /// transformed. `max_lengths` are those of the fields, in the same order.
+    pub(crate) fn new(
+        indexes: Vec<(String, Vec<String>)>,
+        fields: &[String],
+        max_lengths: &[Option<usize>],
+        transformers: &HashMap<String, TransformerPipeline>,
+    ) -> Self {
+        let indexes = indexes
+            .into_iter()
+            .filter_map(|(name, columns)| {
+                let adjusted = columns
+                    .iter()
+                    .find(|column| transformers.contains_key(*column))?;
+                let position = |column: &String| fields.iter().position(|field| field == column);
+
+                let adjusted = position(adjusted)?;
+
+                Some(UniqueIndex {
+                    adjusted,
+                    max_length: max_lengths.get(adjusted).copied().flatten(),
+                    columns: columns.iter().map(position).collect::<Option<_>>()?,
+                    name,
+                    seen: Mutex::new(HashMap::new()),
+                })
+            })
+            .collect();
+
+        Self { indexes }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.indexes.is_empty()
+    }
+
+    /// Record the keys of a row already in the target, as is.
+    pub(crate) fn record(&self, row: &[MysqlValueDecoded]) {
+        for index in self.indexes.iter() {
+            if let Some(key) = index.key(row) {
+                index.seen.lock().unwrap().entry(key).or_insert(0);
+            }
+        }
+    }
+
+    /// Record the keys of a transformed row, changing it first where a key is taken.
+    pub(crate) fn ensure(&self, row: &mut [MysqlValueDecoded]) {
+        for index in self.indexes.iter() {
+            let mut seen = index.seen.lock().unwrap();
+
+            let Some(key) = index.key(row) else {
+                continue;
+            };
+
+            let Some(&tried) = seen.get(&key) else {
+                seen.insert(key, 0);
+                continue;
+            };
+
+            let original = row[index.adjusted].clone();
+            let mut unique = false;
+            let mut attempt = tried;
+
+            while !unique && attempt < tried + MAX_ATTEMPTS {
+                attempt += 1;
+
+                let Some(candidate) = adjust(&original, attempt, index.max_length) else {
+                    break;
+                };
+
+                row[index.adjusted] = candidate;
+
+                if let Some(candidate_key) = index.key(row)
+                    && !seen.contains_key(&candidate_key)
+                {
+                    seen.insert(candidate_key, 0);
+                    unique = true;
+                }
+            }
+
+            seen.insert(key, attempt);
+
+            if !unique {
+                tracing::warn!("cannot make {:?} unique for index {}", original, index.name);
+
+                row[index.adjusted] = original;
+            }
+        }
+    }
+}
+
+impl UniqueIndex {
+    fn key(&self, row: &[MysqlValueDecoded]) -> Option<u64> {
+        let mut hasher = DefaultHasher::new();
+
+        for column in self.columns.iter() {
+            let text = row.get(*column)?.as_text()?;
+            deunicode::deunicode(&text.to_lowercase())
+                .trim_end_matches(' ')
+                .hash(&mut hasher);
+        }
+
+        Some(hasher.finish())
+    }
+}
+
+/// The `attempt`th other value tried in place of a value whose key is taken. Text is cut
+/// before its suffix to fit in `max_length`, or MySQL would cut the suffix off instead.
+fn adjust(
+    value: &MysqlValueDecoded,
+    attempt: u32,
+    max_length: Option<usize>,
+) -> Option<MysqlValueDecoded> {
+    let suffix = format!("_{}", attempt + 1);
+    // Room left for the start of the value once the suffix and what follows are added.
+    let room = |rest: usize| match max_length {
+        Some(max_length) => max_length.checked_sub(suffix.len() + rest),
+        None => Some(usize::MAX),
+    };
+
+    Some(match value {
+        MysqlValueDecoded::Int(i) => MysqlValueDecoded::Int(i.checked_add(attempt.into())?),
+        MysqlValueDecoded::UInt(u) => MysqlValueDecoded::UInt(u.checked_add(attempt.into())?),
+        MysqlValueDecoded::Double(f) => MysqlValueDecoded::Double(f + f64::from(attempt)),
+        MysqlValueDecoded::Decimal(d) => {
+            MysqlValueDecoded::Decimal(d.checked_add(Decimal::from(attempt))?)
+        }
+        MysqlValueDecoded::DateTime(datetime) => MysqlValueDecoded::DateTime(
+            datetime.checked_add_signed(TimeDelta::seconds(attempt.into()))?,
+        ),
+        MysqlValueDecoded::String(s) => {
+            let at = s.find('@').unwrap_or(s.len());
+            let start = s[..at]
+                .chars()
+                .take(room(s[at..].chars().count())?)
+                .collect::<String>();
+
+            MysqlValueDecoded::String(format!("{}{}{}", start, suffix, &s[at..]))
+        }
+        MysqlValueDecoded::Bytes(bytes) => {
+            let mut bytes = bytes.clone();
+            bytes.truncate(room(0)?);
+            bytes.extend_from_slice(suffix.as_bytes());
+
+            MysqlValueDecoded::Bytes(bytes)
+        }
+        MysqlValueDecoded::Null | MysqlValueDecoded::Bool(_) => return None,
+    })
+}
@@ -0,0 +1,341 @@
+use crate::config::MigrateConfig;
+use crate::transformer::{Transformer, TransformerPipeline, transform_order};
+use crate::value::MysqlValueDecoded;
+use sqlx::{MySqlPool, Row};
+use std::collections::{BTreeMap, HashMap};
+
+const SELECT_COLUMNS: &str = "SELECT `TABLE_NAME`, `COLUMN_NAME`, `DATA_TYPE`, `COLUMN_TYPE`, `IS_NULLABLE`, CAST(`CHARACTER_MAXIMUM_LENGTH` AS SIGNED) FROM `INFORMATION_SCHEMA`.`COLUMNS` WHERE `TABLE_SCHEMA` = DATABASE()";
+const SELECT_UNIQUE_INDEXES: &str = "SELECT `TABLE_NAME`, `INDEX_NAME`, `COLUMN_NAME` FROM `INFORMATION_SCHEMA`.`STATISTICS` WHERE `TABLE_SCHEMA` = DATABASE() AND `NON_UNIQUE` = 0 AND `COLUMN_NAME` IS NOT NULL ORDER BY `TABLE_NAME`, `INDEX_NAME`, `SEQ_IN_INDEX`";
+
+struct Column {
+    data_type: String,
+    column_type: String,
+    nullable: bool,
+    /// In characters for text columns, in bytes for binary ones.
+    max_length: Option<i64>,
+}
+
+/// Check the transformers against the columns they apply to, so a value the target cannot
+/// store stops the migration before it starts instead of failing batches midway. Target
+/// tables are created from the source ones, whose columns are read here. Tables missing
+/// from the source are ignored, as they are when migrating.
+///
+/// Returns the problems found, one message each.
+pub(crate) async fn validate_transformers(
+    pool: &MySqlPool,
+    config: &MigrateConfig,
+) -> Result<Vec<String>, sqlx::Error> {
+    let mut tables: HashMap<String, HashMap<String, Column>> = HashMap::new();
+
+    for row in sqlx::query(SELECT_COLUMNS).fetch_all(pool).await? {
+        tables.entry(row.try_get(0)?).or_default().insert(
+            row.try_get(1)?,
+            Column {
+                data_type: row.try_get::<String, usize>(2)?.to_lowercase(),
+                column_type: row.try_get(3)?,
+                nullable: row.try_get::<String, usize>(4)? == "YES",
+                max_length: row.try_get(5)?,
+            },
+        );
+    }
+
+    let mut unique_indexes: HashMap<String, BTreeMap<String, Vec<String>>> = HashMap::new();
+
+    for row in sqlx::query(SELECT_UNIQUE_INDEXES).fetch_all(pool).await? {
+        unique_indexes
+            .entry(row.try_get(0)?)
+            .or_default()
+            .entry(row.try_get(1)?)
+            .or_default()
+            .push(row.try_get(2)?);
+    }
+
+    let mut problems = Vec::new();
+    let configured = config.tables.iter().collect::<BTreeMap<_, _>>();
+
+    for (table, table_config) in configured {
+        let Some(columns) = tables.get(table) else {
+            continue;
+        };
+
+        let transformers = table_config.transformers.iter().collect::<BTreeMap<_, _>>();
+
+        for (name, pipeline) in transformers {
+            let Some(column) = columns.get(name) else {
+                problems.push(format!("{}.{}: no such column", table, name));
+                continue;
+            };
+
+            for step in pipeline.0.iter() {
+                if let Err(reason) = check_transformer(&step.transformer, column) {
+                    problems.push(format!(
+                        "{}.{}: {} ({})",
+                        table, name, reason, column.column_type
+                    ));
+                }
+
+                if let Transformer::Replace(replacement) = &step.transformer
+                    && let Err(reason) = check_value(replacement.value(), column)
+                {
+                    problems.push(format!(
+                        "{}.{}: replacement {:?} {} ({})",
+                        table,
+                        name,
+                        replacement.value(),
+                        reason,
+                        column.column_type
+                    ));
+                }
+            }
+        }
+
+        // The order only matters to the columns reading each other, the table's is not needed
+        // to find the ones which cannot be ordered.
+        let names = table_config
+            .transformers
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        if let Err(reason) = transform_order(&table_config.transformers, &names) {
+            problems.push(format!("{}: {}", table, reason));
+        }
+
+        // Transformers making values unique cannot do anything for a constant: every row
+        // but the first would fail with a duplicate key.
+        for (index, index_columns) in unique_indexes.get(table).into_iter().flatten() {
+            let constants = index_columns
+                .iter()
+                .map(|column| {
+                    table_config
+                        .transformers
+                        .get(column)
+                        .and_then(TransformerPipeline::constant)
+                })
+                .collect::<Option<Vec<_>>>();
+
+            if let Some(constants) = constants
+                && !constants
+                    .iter()
+                    .any(|value| matches!(value, MysqlValueDecoded::Null))
+            {
+                problems.push(format!(
+                    "{}.{}: constant replacement on the unique index {}",
+                    table,
+                    index_columns.join(", "),
+                    index
+                ));
+            }
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Why the transformer cannot apply to the column, if it cannot.
+fn check_transformer(transformer: &Transformer, column: &Column) -> Result<(), String> {
+    match transformer {
+        // Encrypted numbers would lose their leading zeros, and with them the way back.
+        Transformer::Encrypt(_) if !is_text(column) => {
+            Err("encrypt only applies to text and binary columns".to_string())
+        }
+        // MySQL would store text as 0, or as the number it starts with: rows would silently
+        // get the same value, or keep part of the original one.
+        Transformer::Pseudonymize(pseudonymize)
+            if is_numeric(column) && !pseudonymize.yields_numbers() =>
+        {
+            Err("pseudonymize only gives numbers with format: numeric".to_string())
+        }
+        Transformer::Fake(_) if is_numeric(column) => {
+            Err("fake gives text, not numbers".to_string())
+        }
+        Transformer::Mask(mask) if is_numeric(column) && !mask.yields_numbers() => {
+            Err("mask only gives numbers with a digit as char".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn is_numeric(column: &Column) -> bool {
+    matches!(
+        column.data_type.as_str(),
+        "tinyint"
+            | "smallint"
+            | "mediumint"
+            | "int"
+            | "integer"
+            | "bigint"
+            | "decimal"
+            | "numeric"
+            | "float"
+            | "double"
+            | "real"
+            | "year"
+            | "bit"
+    )
+}
+
+fn is_text(column: &Column) -> bool {
+    matches!(
+        column.data_type.as_str(),
+        "char"
+            | "varchar"
+            | "tinytext"
+            | "text"
+            | "mediumtext"
+            | "longtext"
+            | "binary"
+            | "varbinary"
+            | "tinyblob"
+            | "blob"
+            | "mediumblob"
+            | "longblob"
+    )
+}
+
+/// Why the column cannot hold the value, if it cannot.
+fn check_value(value: &MysqlValueDecoded, column: &Column) -> Result<(), String> {
+    use MysqlValueDecoded::*;
+
+    let unsupported = || Err(format!("does not fit a {} column", column.data_type));
+
+    match (value, column.data_type.as_str()) {
+        (Null, _) if column.nullable => Ok(()),
+        (Null, _) => Err("is NULL but the column is NOT NULL".to_string()),
+        (
+            _,
+            "tinyint" | "smallint" | "mediumint" | "int" | "integer" | "bigint" | "year" | "bit",
+        ) => check_integer(value, column),
+        (
+            Bool(_) | Int(_) | UInt(_) | Double(_) | Decimal(_),
+            "decimal" | "numeric" | "float" | "double" | "real" | "json",
+        ) => Ok(()),
+        (String(s), "decimal" | "numeric" | "float" | "double" | "real") => {
+            match s.trim().parse::<f64>() {
+                Ok(_) => Ok(()),
+                Err(_) => Err("is not a number".to_string()),
+            }
+        }
+        (String(s), "json") => match serde_json::from_str::<serde_json::Value>(s) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("is not valid json: {}", e)),
+        },
+        (DateTime(_) | String(_), "date" | "datetime" | "timestamp" | "time") => Ok(()),
+        (String(s), "enum") if !allowed_values(&column.column_type).contains(s) => {
+            Err("is not one of the values of the enum".to_string())
+        }
+        (String(s), "set")
+            if s.split(',').any(|member| {
+                !allowed_values(&column.column_type)
+                    .iter()
+                    .any(|v| v == member)
+            }) =>
+        {
+            Err("is not made of the values of the set".to_string())
+        }
+        (String(_), "enum" | "set") => Ok(()),
+        (
+            Bytes(_) | String(_),
+            "binary" | "varbinary" | "tinyblob" | "blob" | "mediumblob" | "longblob",
+        ) => {
+            let length = value.as_bytes().map_or(0, |bytes| bytes.len());
+
+            check_length(length, column)
+        }
+        (Bytes(bytes), _) if std::str::from_utf8(bytes).is_err() => unsupported(),
+        (_, "char" | "varchar" | "tinytext" | "text" | "mediumtext" | "longtext") => {
+            let length = value.as_text().map_or(0, |text| text.chars().count());
+
+            check_length(length, column)
+        }
+        // Spatial types and such are not checked.
+        (
+            _,
+            "geometry" | "point" | "linestring" | "polygon" | "multipoint" | "multilinestring"
+            | "multipolygon" | "geometrycollection" | "geomcollection" | "vector",
+        ) => Ok(()),
+        _ => unsupported(),
+    }
+}
+
+fn check_length(length: usize, column: &Column) -> Result<(), String> {
+    match column.max_length {
+        Some(max_length) if length as i64 > max_length => {
+            Err(format!("is longer than the {} allowed", max_length))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_integer(value: &MysqlValueDecoded, column: &Column) -> Result<(), String> {
+    let integer = match value {
+        MysqlValueDecoded::Bool(b) => Some(i128::from(*b)),
+        MysqlValueDecoded::Int(_) | MysqlValueDecoded::UInt(_) => value.as_i128(),
+        MysqlValueDecoded::Double(_)
+        | MysqlValueDecoded::Decimal(_)
+        | MysqlValueDecoded::String(_) => value
+            .as_text()
+            .and_then(|text| text.trim().parse::<f64>().ok())
+            .filter(|f| f.fract() == 0.0)
+            .map(|f| f as i128),
+        _ => None,
+    };
+
+    let Some(integer) = integer else {
+        return Err("is not an integer".to_string());
+    };
+
+    let unsigned = column.column_type.contains("unsigned");
+    let bits = match column.data_type.as_str() {
+        "tinyint" => 8,
+        "smallint" => 16,
+        "mediumint" => 24,
+        "int" | "integer" => 32,
+        "year" => return check_range(integer, 0, 2155),
+        "bit" => return check_range(integer, 0, u64::MAX as i128),
+        _ => 64,
+    };
+
+    if unsigned {
+        check_range(integer, 0, (1i128 << bits) - 1)
+    } else {
+        check_range(integer, -(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1)
+    }
+}
+
+fn check_range(integer: i128, min: i128, max: i128) -> Result<(), String> {
+    if (min..=max).contains(&integer) {
+        Ok(())
+    } else {
+        Err(format!("is out of the range {} to {}", min, max))
+    }
+}
+
+/// Values of an `enum('a','b')` or `set('a','b')` column type, quotes being doubled inside
+/// them.
+fn allowed_values(column_type: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut chars = column_type.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\'' {
+            continue;
+        }
+
+        let mut value = String::new();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\'' if chars.peek() == Some(&'\'') => {
+                    chars.next();
+                    value.push('\'');
+                }
+                '\'' => break,
+                c => value.push(c),
+            }
+        }
+
+        values.push(value);
+    }
+
+    values
+}
@@ -2,9 +2,10 @@ use chrono::Utc;
 use sqlx::mysql::MySqlValue;
 use sqlx::types::Decimal;
 use sqlx::{TypeInfo, Value};
+use std::borrow::Cow;
 use std::fmt;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) enum MysqlValueDecoded {
     Null,
     Bool(bool),
@@ -36,6 +37,58 @@ impl fmt::Display for ValueError {
     }
 }
 
+impl MysqlValueDecoded {
+    /// The value as an integer wide enough for both signed and unsigned columns, None when
+    /// it is not an integer.
+    pub(crate) fn as_i128(&self) -> Option<i128> {
+        match self {
+            MysqlValueDecoded::Int(i) => Some(*i as i128),
+            MysqlValueDecoded::UInt(u) => Some(*u as i128),
+            _ => None,
+        }
+    }
+
+    /// The value as the bytes of its text form, None for NULL. Values equal once rendered
+    /// as text give the same bytes, whatever their column type.
+    pub(crate) fn as_bytes(&self) -> Option<Cow<'_, [u8]>> {
+        Some(match self {
+            MysqlValueDecoded::Null => return None,
+            MysqlValueDecoded::Bool(b) => Cow::Borrowed(if *b { b"1" } else { b"0" }),
+            MysqlValueDecoded::Int(i) => Cow::Owned(i.to_string().into_bytes()),
+            MysqlValueDecoded::UInt(u) => Cow::Owned(u.to_string().into_bytes()),
+            MysqlValueDecoded::Double(f) => Cow::Owned(f.to_string().into_bytes()),
+            MysqlValueDecoded::Decimal(d) => Cow::Owned(d.to_string().into_bytes()),
+            MysqlValueDecoded::String(s) => Cow::Borrowed(s.as_bytes()),
+            MysqlValueDecoded::DateTime(dt) => {
+                Cow::Owned(dt.format("%Y-%m-%d %H:%M:%S%.f").to_string().into_bytes())
+            }
+            MysqlValueDecoded::Bytes(bytes) => Cow::Borrowed(bytes.as_slice()),
+        })
+    }
+
+    /// The value rendered as text, None for NULL. Invalid UTF-8 is replaced.
+    pub(crate) fn as_text(&self) -> Option<Cow<'_, str>> {
+        Some(match self.as_bytes()? {
+            Cow::Borrowed(bytes) => String::from_utf8_lossy(bytes),
+            Cow::Owned(bytes) => Cow::Owned(String::from_utf8_lossy(&bytes).into_owned()),
+        })
+    }
+
+    /// Rough number of bytes the value takes once sent to the target, to size batches.
+    pub(crate) fn size(&self) -> usize {
+        match self {
+            MysqlValueDecoded::Null | MysqlValueDecoded::Bool(_) => 1,
+            MysqlValueDecoded::Int(_)
+            | MysqlValueDecoded::UInt(_)
+            | MysqlValueDecoded::Double(_)
+            | MysqlValueDecoded::DateTime(_) => 8,
+            MysqlValueDecoded::Decimal(_) => 16,
+            MysqlValueDecoded::String(s) => s.len(),
+            MysqlValueDecoded::Bytes(bytes) => bytes.len(),
+        }
+    }
+}
+
 /// Values are fetched over the MySQL text protocol, so anything can be read back as text.
 /// Fallback to raw bytes when the content is not valid UTF-8.
 fn decode_text_or_bytes(value: &MySqlValue) -> Result<MysqlValueDecoded, ValueError> {